@@ -0,0 +1,58 @@
+use deno_core::JsRuntime;
+use deno_core::RuntimeOptions;
+use rusty_v8 as v8;
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[path = "src/extensions.rs"]
+mod extensions;
+
+// snapshot = deno runtime js + skynet_ts plugin js, with every extension group of create_runtime.
+// the bootstrap can't be snapshotted with deno_core 0.97: it registers the macrotask callback in
+// the rust side JsRuntime state and freezes per service values (Deno.pid, Deno.args) on the Deno namespace
+fn create_snapshot(snapshot_path: &Path, files: Vec<PathBuf>) {
+    let extensions = extensions::deno_extensions(&|_: &str| true);
+
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        will_snapshot: true,
+        extensions: extensions,
+        ..Default::default()
+    });
+    {
+        let _isolate_scope = v8::IsolateScope::new(&mut runtime.v8_isolate());
+        let _locker = v8::Locker::new(&mut runtime.v8_isolate(), std::ptr::null_mut());
+        let _auto_check = deno_core::IsolateAutoCheck::new(&mut runtime.v8_isolate());
+
+        for file in files {
+            println!("cargo:rerun-if-changed={}", file.display());
+            let name = file.file_name().unwrap().to_string_lossy().to_string();
+            let prefix = if file.starts_with("src") { "skynet_ts:" } else { "deno:runtime/js/" };
+            let source = std::fs::read_to_string(&file).unwrap();
+            runtime.execute_script(&(prefix.to_string() + &name), &source).unwrap();
+        }
+    }
+
+    let snapshot = runtime.snapshot();
+    let snapshot_slice: &[u8] = &*snapshot;
+    std::fs::write(snapshot_path, snapshot_slice).unwrap();
+}
+
+fn get_js_files(dir: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().unwrap_or_default() == "js")
+        .collect();
+    files.sort();
+    files
+}
+
+fn main() {
+    let o = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let snapshot_path = o.join("SKYNET_SNAPSHOT.bin");
+
+    let mut js_files = get_js_files("deno/runtime/js");
+    js_files.append(&mut get_js_files("src"));
+    create_snapshot(&snapshot_path, js_files);
+}
@@ -58,6 +58,88 @@
         return session;
     }
 
+    // skynet_malloc messages live in the resource table, the ones never handed to skynet are freed once collected.
+    // created on first use, this file is part of the startup snapshot
+    let messageRegistry;
+    function message_registry() {
+        if (!messageRegistry) {
+            messageRegistry = new FinalizationRegistry((rid) => {
+                try {
+                    core.close(rid);
+                } catch {
+                    // already taken by an op
+                }
+            });
+        }
+        return messageRegistry;
+    }
+
+    class Message {
+        #rid;
+        sz;
+
+        constructor(rid, sz) {
+            this.#rid = rid;
+            this.sz = sz;
+            message_registry().register(this, rid, this);
+        }
+
+        get rid() {
+            if (this.#rid === undefined) {
+                throw new TypeError("Message already consumed");
+            }
+            return this.#rid;
+        }
+
+        // ownership moves to the op, the message can't be used again
+        take() {
+            const rid = this.rid;
+            this.#rid = undefined;
+            message_registry().unregister(this);
+            return rid;
+        }
+    }
+
+    function send_msg(addr, ptype, session, msg) {
+        if (session == null) {
+            // PTYPE_TAG_ALLOCSESSION
+            ptype |= 0x20000
+            session = 0
+        }
+
+        session = core.opRawSync("op_skynet_send_msg", addr, ptype, session, msg.take());
+        if (session < 0) {
+            if (session == -2) {
+                return false;
+            }
+            return null;
+        }
+        return session;
+    }
+
+    // resolves to the response Message, the caller frees it
+    async function call(addr, ptype, timeout, ...bufs) {
+        const rsp = await core.opAsync("op_skynet_call", { dest: addr, ptype: ptype, timeout: timeout || 0 }, bufs);
+        return new Message(rsp.msg, rsp.sz);
+    }
+
+    async function call_msg(addr, ptype, timeout, msg) {
+        const rsp = await core.opAsync("op_skynet_call", { dest: addr, ptype: ptype, timeout: timeout || 0, msg: msg.take() });
+        return new Message(rsp.msg, rsp.sz);
+    }
+
+    function timeout(ti) {
+        return core.opSync("op_skynet_timeout", ti);
+    }
+
+    function sleep(session) {
+        return core.opAsync("op_skynet_sleep", session);
+    }
+
+    function wakeup(session) {
+        return core.opSync("op_skynet_wakeup", session);
+    }
+
     function now() {
         let r = core.opSync("op_skynet_now");
         return r;
@@ -71,17 +153,25 @@
         command("EXIT")
     }
 
+    function tokio_stats() {
+        return core.opSync("op_skynet_tokio_stats");
+    }
+
+    function wake_stats() {
+        return core.opSync("op_skynet_wake_stats");
+    }
+
     function genid() {
         return core.opSync("op_skynet_genid");
     }
 
     function fetch_message(msg, sz, buffer, offset) {
-        let len = core.opRawSync("op_skynet_fetch_message", msg, sz, buffer, offset);
+        let len = core.opRawSync("op_skynet_fetch_message", msg.rid, sz, buffer, offset);
         return len;
     }
 
     function free(msg) {
-        core.opRawSync("op_skynet_free", msg);
+        core.opRawSync("op_skynet_free", msg.take());
     }
 
     function socket_connect(addr, port) {
@@ -100,6 +190,19 @@
         return core.opRawSync("op_skynet_socket_unpack", ptr1, ptr2, sz);
     }
 
+    // the socket messages of id go to the returned resource from now on, see socket/conn.ts
+    function socket_stream(id, closed, buffered) {
+        return core.opSync("op_skynet_socket_stream", { id, closed }, buffered);
+    }
+
+    function socket_read(rid, buffer) {
+        return core.opAsync("op_skynet_socket_read", rid, buffer);
+    }
+
+    function socket_write(rid, buffer) {
+        return core.opAsync("op_skynet_socket_write", rid, buffer);
+    }
+
     function socket_bind(fd) {
         return core.opSync("op_skynet_socket_bind", fd);
     }
@@ -121,19 +224,44 @@
     }
 
     function alloc_msg(...buffers) {
-        return core.opRawSync("op_skynet_alloc_msg", ...buffers);
+        const [rid, sz] = core.opRawSync("op_skynet_alloc_msg", ...buffers);
+        return new Message(rid, sz);
+    }
+
+    function lua_userdata(ctor) {
+        core.opRawSync("op_skynet_lua_userdata", ctor);
+    }
+
+    function lua_pack(...values) {
+        const [rid, sz] = core.opRawSync("op_skynet_lua_pack", ...values);
+        return new Message(rid, sz);
     }
 
-    function socket_send(id, msg, len) {
-        return core.opRawSync("op_skynet_socket_send", id, msg, len);
+    function lua_unpack(msg, offset, sz) {
+        if (msg instanceof Message) {
+            return core.opRawSync("op_skynet_lua_unpack", msg.rid);
+        }
+        return core.opRawSync("op_skynet_lua_unpack", msg, offset, sz);
+    }
+
+    function socket_send(id, msg) {
+        return core.opRawSync("op_skynet_socket_send", id, msg.take());
+    }
+
+    function socket_send_lowpriority(id, msg) {
+        return core.opRawSync("op_skynet_socket_send_lowpriority", id, msg.take());
     }
 
-    function socket_send_lowpriority(id, msg, len) {
-        return core.opRawSync("op_skynet_socket_send_lowpriority", id, msg, len);
+    function socket_sendto(id, address, msg) {
+        return core.opRawSync("op_skynet_socket_sendto", id, address, msg.take());
     }
 
-    function socket_sendto(id, address, msg, len) {
-        return core.opRawSync("op_skynet_socket_sendto", id, address, msg, len);
+    function socket_sendv(id, ...views) {
+        return core.opRawSync("op_skynet_socket_sendv", id, ...views);
+    }
+
+    function socket_sendv_lowpriority(id, ...views) {
+        return core.opRawSync("op_skynet_socket_sendv_lowpriority", id, ...views);
     }
 
     function socket_nodelay(id) {
@@ -152,34 +280,99 @@
         core.opRawSync("op_skynet_callback", cb);
     }
 
+    function init_args() {
+        return Object.freeze(core.opSync("op_skynet_init_args"));
+    }
+
+    function loader_path() {
+        return core.opSync("op_skynet_loader_path");
+    }
+
     function set_jslib_paths(paths) {
         return core.opSync("op_skynet_set_jslib_paths", paths);
     }
 
+    // ids of the scheduled timers, cleared on exit so they don't keep the event loop alive
+    const live_timers = new Set();
+    let clear_timer;
+
+    // called once after bootstrap, the deno timers don't expose what is still scheduled
+    function hook_timers() {
+        const { setTimeout, setInterval, clearTimeout } = window;
+        clear_timer = clearTimeout;
+        window.setTimeout = function (cb, delay = 0, ...args) {
+            let callback = cb;
+            if (typeof cb == "function") {
+                callback = (...a) => {
+                    live_timers.delete(id);
+                    return cb.apply(window, a);
+                };
+            }
+            const id = setTimeout.call(this, callback, delay, ...args);
+            live_timers.add(id);
+            return id;
+        };
+        window.setInterval = function (cb, delay = 0, ...args) {
+            const id = setInterval.call(this, cb, delay, ...args);
+            live_timers.add(id);
+            return id;
+        };
+        window.clearTimeout = window.clearInterval = function (id = 0) {
+            live_timers.delete(id);
+            clearTimeout.call(this, id);
+        };
+    }
+
+    function clear_timers() {
+        for (const id of live_timers) {
+            clear_timer(id);
+        }
+        live_timers.clear();
+    }
+
     let skynet = {
         command,
         get_env,
         set_env,
         send,
+        send_msg,
+        call,
+        call_msg,
         now,
+        timeout,
+        sleep,
+        wakeup,
         error,
         exit,
         addresscommand,
         intcommand,
         genid,
+        tokio_stats,
+        wake_stats,
         fetch_message,
         free,
         shared_bs,
         shared_bs_temp,
         callback,
         set_jslib_paths,
+        init_args,
+        loader_path,
+        hook_timers,
+        clear_timers,
         alloc_msg,
+        Message,
+        lua_userdata,
+        lua_pack,
+        lua_unpack,
 
 
         socket_connect,
         socket_close,
         socket_shutdown,
         socket_unpack,
+        socket_stream,
+        socket_read,
+        socket_write,
         socket_bind,
         socket_start,
         socket_listen,
@@ -188,6 +381,8 @@
         socket_send,
         socket_send_lowpriority,
         socket_sendto,
+        socket_sendv,
+        socket_sendv_lowpriority,
         socket_nodelay,
     };
     window.Skynet = skynet;
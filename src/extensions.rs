@@ -0,0 +1,70 @@
+use deno_runtime::ops;
+
+// the deno extensions of a service, build.rs snapshots the js of all groups from the same list
+// so the snapshot and the runtime agree on the ops and extension flags
+pub fn deno_extensions(enabled: &dyn Fn(&str) -> bool) -> Vec<deno_core::Extension> {
+    // Internal modules
+    let mut extensions: Vec<deno_core::Extension> = vec![
+        // Web APIs
+        deno_runtime::deno_webidl::init(),
+        deno_runtime::deno_console::init(),
+        deno_runtime::deno_url::init(),
+        deno_runtime::deno_web::init(deno_runtime::deno_web::BlobStore::default(), None),
+        deno_runtime::deno_timers::init::<deno_runtime::permissions::Permissions>(),
+        // Metrics
+        deno_runtime::metrics::init(),
+        // Runtime ops
+        ops::runtime::init(deno_core::ModuleSpecifier::parse("file:///mainmodule").unwrap()),
+        //ops::worker_host::init(options.create_web_worker_cb.clone()),
+        ops::io::init(),
+        ops::io::init_stdio(),
+        ops::os::init(),
+        ops::permissions::init(),
+    ];
+    if enabled("web") {
+        extensions.push(deno_runtime::deno_fetch::init::<deno_runtime::permissions::Permissions>(
+            "".to_string(),
+            None,
+            None,
+            None,
+            None,
+        ));
+        extensions.push(deno_runtime::deno_websocket::init::<deno_runtime::permissions::Permissions>(
+            "".to_string(),
+            None,
+            None,
+        ));
+        //deno_runtime::deno_webstorage::init(options.location_data_dir.clone()),
+        extensions.push(deno_runtime::deno_crypto::init(None));
+        extensions.push(deno_runtime::deno_broadcast_channel::init(
+            deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel::default(),
+            true,
+        ));
+    }
+    if enabled("webgpu") {
+        extensions.push(deno_runtime::deno_webgpu::init(true));
+    }
+    if enabled("ffi") {
+        extensions.push(deno_runtime::deno_ffi::init::<deno_runtime::permissions::Permissions>(true));
+    }
+    if enabled("fs") {
+        extensions.push(ops::fs_events::init());
+        extensions.push(ops::fs::init());
+    }
+    if enabled("net") {
+        extensions.push(deno_runtime::deno_tls::init());
+        extensions.push(deno_runtime::deno_net::init::<deno_runtime::permissions::Permissions>(
+            None,
+            true,
+            None,
+        ));
+        extensions.push(deno_runtime::deno_http::init());
+        extensions.push(ops::http::init());
+    }
+    if enabled("process") {
+        extensions.push(ops::process::init());
+        extensions.push(ops::signal::init());
+        extensions.push(ops::tty::init());
+    }
+    extensions
+}
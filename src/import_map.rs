@@ -0,0 +1,144 @@
+use deno_core::error::generic_error;
+use deno_core::error::AnyError;
+use deno_core::ModuleSpecifier;
+use serde_json::Value;
+
+// specifier key -> address, longest key first. a null or invalid address blocks the key
+type SpecifierMap = Vec<(String, Option<ModuleSpecifier>)>;
+
+// https://github.com/WICG/import-maps, `imports` and `scopes` of one json file
+pub struct ImportMap {
+    imports: SpecifierMap,
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+fn is_url_like(specifier: &str) -> bool {
+    specifier.starts_with('/') || specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+// absolute urls are normalized, bare specifiers stay as they are
+fn normalize_bare(specifier: &str) -> String {
+    match ModuleSpecifier::parse(specifier) {
+        Ok(url) => url.to_string(),
+        Err(_) => specifier.to_string(),
+    }
+}
+
+// relative keys are resolved like addresses
+fn normalize_key(base: &ModuleSpecifier, key: &str) -> String {
+    if is_url_like(key) {
+        if let Ok(url) = base.join(key) {
+            return url.to_string();
+        }
+    }
+    normalize_bare(key)
+}
+
+fn parse_address(base: &ModuleSpecifier, key: &str, address: &Value) -> Option<ModuleSpecifier> {
+    let address = address.as_str()?;
+    let url = if is_url_like(address) {
+        base.join(address).ok()?
+    } else {
+        ModuleSpecifier::parse(address).ok()?
+    };
+    // a prefix key must map to a prefix
+    if key.ends_with('/') && !url.as_str().ends_with('/') {
+        return None;
+    }
+    Some(url)
+}
+
+fn parse_specifier_map(base: &ModuleSpecifier, value: &Value) -> Result<SpecifierMap, AnyError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| generic_error("import map specifier map must be an object"))?;
+    let mut map: SpecifierMap = object
+        .iter()
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, address)| {
+            let key = normalize_key(base, key);
+            let address = parse_address(base, &key, address);
+            (key, address)
+        })
+        .collect();
+    map.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| b.cmp(a)));
+    Ok(map)
+}
+
+fn resolve_in(map: &SpecifierMap, specifier: &str) -> Result<Option<ModuleSpecifier>, AnyError> {
+    for (key, address) in map {
+        if key == specifier {
+            let address = address
+                .as_ref()
+                .ok_or_else(|| generic_error(format!("import of \"{}\" is blocked by the import map", specifier)))?;
+            return Ok(Some(address.clone()));
+        }
+
+        if key.ends_with('/') && specifier.starts_with(key.as_str()) {
+            let address = address
+                .as_ref()
+                .ok_or_else(|| generic_error(format!("import of \"{}\" is blocked by the import map", specifier)))?;
+            let url = address.join(&specifier[key.len()..])?;
+            // "../" in the rest of the specifier must not leave the mapped prefix
+            if !url.as_str().starts_with(address.as_str()) {
+                return Err(generic_error(format!(
+                    "import of \"{}\" backtracks above its prefix \"{}\"",
+                    specifier, key
+                )));
+            }
+            return Ok(Some(url));
+        }
+    }
+    Ok(None)
+}
+
+impl ImportMap {
+    pub fn load(path: &str) -> Result<ImportMap, AnyError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| generic_error(format!("can not read import map {}: {}", path, err)))?;
+        let base = ModuleSpecifier::from_file_path(std::env::current_dir()?.join(path))
+            .map_err(|_| generic_error(format!("invalid import map path {}", path)))?;
+        ImportMap::parse(&base, &json)
+            .map_err(|err| generic_error(format!("invalid import map {}: {}", path, err)))
+    }
+
+    pub fn parse(base: &ModuleSpecifier, json: &str) -> Result<ImportMap, AnyError> {
+        let value: Value = serde_json::from_str(json)?;
+        let imports = match value.get("imports") {
+            Some(imports) => parse_specifier_map(base, imports)?,
+            None => Vec::new(),
+        };
+
+        let mut scopes = Vec::new();
+        if let Some(value) = value.get("scopes") {
+            let object = value
+                .as_object()
+                .ok_or_else(|| generic_error("import map scopes must be an object"))?;
+            for (scope, map) in object {
+                let scope = base.join(scope)?.to_string();
+                scopes.push((scope, parse_specifier_map(base, map)?));
+            }
+        }
+        scopes.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| b.cmp(a)));
+
+        Ok(ImportMap { imports, scopes })
+    }
+
+    // the most specific scope of the referrer first, then the top level imports
+    pub fn resolve(&self, specifier: &str, referrer: &str) -> Result<Option<ModuleSpecifier>, AnyError> {
+        let specifier = if is_url_like(specifier) {
+            deno_core::resolve_import(specifier, referrer)?.to_string()
+        } else {
+            normalize_bare(specifier)
+        };
+
+        for (scope, map) in &self.scopes {
+            if referrer == scope || (scope.ends_with('/') && referrer.starts_with(scope.as_str())) {
+                if let Some(url) = resolve_in(map, &specifier)? {
+                    return Ok(Some(url));
+                }
+            }
+        }
+        resolve_in(&self.imports, &specifier)
+    }
+}
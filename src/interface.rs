@@ -10,6 +10,10 @@ pub struct skynet_socket_message {
     pub buffer: *const u8,
 }
 pub const SKYNET_SOCKET_MESSAGE_SIZE: usize = 24;
+// socket_sendbuffer.msg_type
+//pub const SOCKET_BUFFER_MEMORY: c_int = 0;
+//pub const SOCKET_BUFFER_OBJECT: c_int = 1;
+pub const SOCKET_BUFFER_RAWPOINTER: c_int = 2;
 #[repr(C)]
 pub struct socket_sendbuffer {
     pub id: c_int,
@@ -20,7 +24,7 @@ pub struct socket_sendbuffer {
 
 #[cfg_attr(target_os = "windows", link(name = "skynet"))]
 extern "C" {
-    pub fn skynet_malloc(size: c_uint) -> *mut c_void;
+    pub fn skynet_malloc(size: size_t) -> *mut c_void;
     pub fn skynet_free(ptr: *const c_void);
     pub fn skynet_send(
         skynet: *const c_void,
@@ -40,6 +44,7 @@ extern "C" {
         msg: *const c_void,
         size: usize,
     ) -> c_int;
+    pub fn skynet_queryname(skynet: *const c_void, name: *const c_char) -> c_uint;
     pub fn skynet_callback(skynet: *const c_void, ctx: *const c_void, cb: *const c_void);
     pub fn skynet_thread_notify_callback(
         skynet: *const c_void,
@@ -53,6 +58,7 @@ extern "C" {
     ) -> *const c_char;
     pub fn skynet_error(skynet: *const c_void, err: *const c_char);
     pub fn skynet_now() -> u64;
+    pub fn skynet_timeout(handle: u32, time: c_int, session: c_int) -> c_int;
 
     pub fn skynet_socket_connect(skynet: *const c_void, host: *const c_char, port: c_int) -> c_int;
     pub fn skynet_socket_close(skynet: *const c_void, id: c_int) -> c_void;
@@ -63,6 +69,7 @@ extern "C" {
     ) -> *const c_char;
     pub fn skynet_socket_bind(skynet: *const c_void, fd: c_int) -> c_int;
     pub fn skynet_socket_start(skynet: *const c_void, id: c_int) -> c_void;
+    pub fn skynet_socket_pause(skynet: *const c_void, id: c_int) -> c_void;
     pub fn skynet_socket_listen(
         skynet: *const c_void,
         host: *const c_char,
@@ -89,15 +96,25 @@ extern "C" {
     ) -> c_int;
     pub fn skynet_socket_nodelay(skynet: *const c_void, id: c_int) -> c_void;
 }
+pub const PTYPE_TEXT: c_int = 0;
+pub const PTYPE_RESPONSE: c_int = 1;
+pub const PTYPE_SOCKET: c_int = 6;
+pub const PTYPE_ERROR: c_int = 7;
+
 pub const PTYPE_TAG_DONTCOPY: c_int = 0x10000;
 pub const PTYPE_TAG_ALLOCSESSION: c_int = 0x20000;
 
-//pub const SKYNET_SOCKET_TYPE_DATA: c_int = 1;
+pub const SKYNET_SOCKET_TYPE_DATA: c_int = 1;
 //pub const SKYNET_SOCKET_TYPE_CONNECT: c_int = 2;
-//pub const SKYNET_SOCKET_TYPE_CLOSE: c_int = 3;
+pub const SKYNET_SOCKET_TYPE_CLOSE: c_int = 3;
 //pub const SKYNET_SOCKET_TYPE_ACCEPT: c_int = 4;
-//pub const SKYNET_SOCKET_TYPE_ERROR: c_int = 5;
+pub const SKYNET_SOCKET_TYPE_ERROR: c_int = 5;
 pub const SKYNET_SOCKET_TYPE_UDP: c_int = 6;
 //pub const SKYNET_SOCKET_TYPE_WARNING: c_int = 7;
 
+// skynet.h defines PTYPE_TEXT(0) .. PTYPE_TRACE(12), the message type takes the low byte
+pub const PTYPE_RESERVED_MAX: c_int = 12;
+pub const PTYPE_MAX: c_int = 255;
+
+// default of js_async_ptype
 pub const PTYPE_DENO_ASYNC: c_int = 234;
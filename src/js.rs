@@ -0,0 +1,8 @@
+use deno_core::Snapshot;
+
+// built by build.rs: deno runtime js + 01_skynet.js, 01_tls.js, 01_inspector.js
+pub static SKYNET_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/SKYNET_SNAPSHOT.bin"));
+
+pub fn skynet_isolate_init() -> Snapshot {
+    Snapshot::Static(SKYNET_SNAPSHOT)
+}
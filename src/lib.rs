@@ -4,32 +4,46 @@ use libc::{c_char, c_int, c_void, size_t};
 use std::ffi::CStr;
 use std::mem::drop;
 use std::ptr;
-//use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
 //use std::sync::Arc;
 
-use deno_runtime::ops;
 use rusty_v8 as v8;
 
+mod extensions;
+mod import_map;
 mod interface;
+mod js;
 mod loader;
+mod lua_seri;
 mod mod_skynet;
 pub use mod_skynet::get_backing_store_slice_mut;
 pub use mod_skynet::BufVec;
 mod mod_inspector;
 mod mod_tls;
+mod options;
+mod tokio_pool;
 
 #[repr(C)]
 pub struct snjs<'a> {
     skynet: *const c_void,
     skynet_handle: u32,
-    tokio_rt: *mut tokio::runtime::Runtime,
-    runtime: Box<deno_core::JsRuntime>,
+    tokio_rt: *mut tokio_pool::TokioPool,
+    runtime: Option<Box<deno_core::JsRuntime>>,
     custom_archive: *mut c_void,
     inspector_session_len: usize,
     waker: *mut std::task::Waker,
     waker_context: *mut std::task::Context<'a>,
     context: SkynetContext,
     remainder_message: i32,
+    isolate_handle: Option<v8::IsolateHandle>,
+    running: Mutex<bool>,
+    interrupted: AtomicBool,
+    heap_exceeded: std::sync::Arc<AtomicBool>,
+    alive: std::sync::Arc<Mutex<bool>>,
+    // at most one async wakeup message is queued, the wakes in between are counted only
+    wake_pending: std::sync::Arc<AtomicBool>,
+    wake_stats: std::sync::Arc<WakeStats>,
 
     locker: *mut v8::Locker,
     tokio_guard: *mut tokio::runtime::EnterGuard<'a>,
@@ -37,6 +51,7 @@ pub struct snjs<'a> {
 
 pub struct ContextData {
     skynet: *const libc::c_void,
+    handle: u32,
     ctx: *const libc::c_void,
     cb: Option<v8::Global<v8::Function>>,
     module_search_paths: Vec<String>,
@@ -45,113 +60,33 @@ pub struct ContextData {
     bs_offset: usize,
     bs_flag: i32,
     bs_temp: Option<v8::SharedRef<v8::BackingStore>>,
+    bs_messages: Vec<(c_int, c_int, c_int)>,
+    bs_external: Vec<v8::SharedRef<v8::BackingStore>>,
+    zero_copy_size: usize,
+    bs_flush_size: usize,
+    calls: std::collections::HashMap<c_int, mod_skynet::PendingCall>,
+    timed_out: std::collections::HashMap<c_int, mod_skynet::CallDest>,
+    timers: std::collections::HashMap<c_int, mod_skynet::CallReceiver>,
+    streams: std::collections::HashMap<c_int, deno_core::ResourceId>,
+    resolve_cache: std::collections::HashMap<(String, String), deno_core::ModuleSpecifier>,
+    init_args: Vec<String>,
+    loader_path: String,
 }
 pub type SkynetContext = *mut ContextData;
 
-static mut TOKIO_RT: *mut tokio::runtime::Runtime = ptr::null_mut();
+#[derive(Default)]
+pub struct WakeStats {
+    sent: AtomicU64,
+    suppressed: AtomicU64,
+}
+
 #[no_mangle]
 pub extern "C" fn snjs_create() -> *mut snjs<'static> {
-    static TOKIO_INIT: std::sync::Once = std::sync::Once::new();
-    TOKIO_INIT.call_once(|| {
-        let rt = Box::new(
-            tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .worker_threads(4)
-                .thread_name("tokio-pool")
-                .build()
-                .unwrap(),
-        );
-        unsafe { TOKIO_RT = Box::into_raw(rt) };
-    });
-
-    let plugin_js = vec![
-        ("skynet_ts:01_skynet.js", include_str!("01_skynet.js")),
-        ("skynet_ts:01_tls.js", include_str!("01_tls.js")),
-        ("skynet_ts:01_inspector.js", include_str!("01_inspector.js")),
-    ];
-
-    let perm_ext = deno_core::Extension::builder()
-        .state(move |state| {
-            state.put(deno_runtime::permissions::Permissions::allow_all());
-            state.put(deno_runtime::ops::UnstableChecker { unstable: true });
-            Ok(())
-        })
-        .build();
-    // Internal modules
-    let extensions: Vec<deno_core::Extension> = vec![
-        // Web APIs
-        deno_runtime::deno_webidl::init(),
-        deno_runtime::deno_console::init(),
-        deno_runtime::deno_url::init(),
-        deno_runtime::deno_web::init(deno_runtime::deno_web::BlobStore::default(), None),
-        deno_runtime::deno_fetch::init::<deno_runtime::permissions::Permissions>(
-            "".to_string(),
-            None,
-            None,
-            None,
-            None,
-        ),
-        deno_runtime::deno_websocket::init::<deno_runtime::permissions::Permissions>(
-            "".to_string(),
-            None,
-            None,
-        ),
-        //deno_runtime::deno_webstorage::init(options.location_data_dir.clone()),
-        deno_runtime::deno_crypto::init(None),
-        deno_runtime::deno_broadcast_channel::init(
-            deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel::default(),
-            true,
-        ),
-        deno_runtime::deno_webgpu::init(true),
-        deno_runtime::deno_timers::init::<deno_runtime::permissions::Permissions>(),
-        // ffi
-        deno_runtime::deno_ffi::init::<deno_runtime::permissions::Permissions>(true),
-        // Metrics
-        deno_runtime::metrics::init(),
-        // Runtime ops
-        ops::runtime::init(deno_core::ModuleSpecifier::parse("file:///mainmodule").unwrap()),
-        //ops::worker_host::init(options.create_web_worker_cb.clone()),
-        ops::fs_events::init(),
-        ops::fs::init(),
-        ops::io::init(),
-        ops::io::init_stdio(),
-        deno_runtime::deno_tls::init(),
-        deno_runtime::deno_net::init::<deno_runtime::permissions::Permissions>(
-            None,
-            true,
-            None,
-        ),
-        ops::os::init(),
-        ops::permissions::init(),
-        ops::process::init(),
-        ops::signal::init(),
-        ops::tty::init(),
-        deno_runtime::deno_http::init(),
-        ops::http::init(),
-
-        mod_skynet::init(),
-        mod_tls::init(),
-        mod_inspector::init(),
-        // Permissions ext (worker specific state)
-        perm_ext,
-    ];
-
-    let mut runtime = Box::new(deno_core::JsRuntime::new(deno_core::RuntimeOptions {
-        extensions: extensions,
-        startup_snapshot: Some(deno_runtime::js::deno_isolate_init()),
-        module_loader: Some(std::rc::Rc::new(loader::ModuleLoader::default())),
-        ..Default::default()
-    }));
-    {
-        let _isolate_scope = v8::IsolateScope::new(&mut runtime.v8_isolate());
-        let _locker = v8::Locker::new(&mut runtime.v8_isolate(), std::ptr::null_mut());
-        let _auto_check = deno_core::IsolateAutoCheck::new(&mut runtime.v8_isolate());
-
-        for (filename, source) in plugin_js {
-            let _r = runtime.execute_script(filename, source);
-        }
-    }
+    install_panic_hook();
+    ffi_guard(ptr::null(), create_impl).unwrap_or(ptr::null_mut())
+}
 
+fn create_impl() -> *mut snjs<'static> {
     let custom_archive = unsafe {
         let p = libc::malloc(1024);
         libc::memset(p, 0, 1024);
@@ -161,9 +96,9 @@ pub extern "C" fn snjs_create() -> *mut snjs<'static> {
     let ctx = Box::new(snjs {
         skynet: ptr::null(),
         skynet_handle: 0,
-        runtime: runtime,
+        runtime: None,
         locker: ptr::null_mut(),
-        tokio_rt: unsafe { TOKIO_RT },
+        tokio_rt: ptr::null_mut(),
         tokio_guard: ptr::null_mut(),
         custom_archive: custom_archive,
         inspector_session_len: 0,
@@ -171,14 +106,112 @@ pub extern "C" fn snjs_create() -> *mut snjs<'static> {
         waker_context: ptr::null_mut(),
         context: ptr::null_mut(),
         remainder_message: 0,
+        isolate_handle: None,
+        running: Mutex::new(false),
+        interrupted: AtomicBool::new(false),
+        heap_exceeded: std::sync::Arc::new(AtomicBool::new(false)),
+        alive: std::sync::Arc::new(Mutex::new(true)),
+        wake_pending: std::sync::Arc::new(AtomicBool::new(false)),
+        wake_stats: std::sync::Arc::new(WakeStats::default()),
     });
 
     Box::into_raw(ctx)
 }
 
+fn create_runtime(options: &options::ServiceOptions) -> Box<deno_core::JsRuntime> {
+    let to_paths = |list: Option<Vec<String>>| -> Option<Vec<std::path::PathBuf>> {
+        list.map(|list| list.into_iter().map(std::path::PathBuf::from).collect())
+    };
+    let permissions_options = deno_runtime::permissions::PermissionsOptions {
+        allow_env: options.get_allow_bool("js_allow_env", true),
+        allow_hrtime: options.get_allow_bool("js_allow_hrtime", true),
+        allow_net: options.get_allow_list("js_allow_net", "true"),
+        allow_ffi: to_paths(options.get_allow_list("js_allow_ffi", "true")),
+        allow_read: to_paths(options.get_allow_list("js_allow_read", "true")),
+        allow_run: options.get_allow_list("js_allow_run", "true"),
+        allow_write: to_paths(options.get_allow_list("js_allow_write", "true")),
+        prompt: false,
+    };
+
+    let perm_ext = deno_core::Extension::builder()
+        .state(move |state| {
+            state.put(deno_runtime::permissions::Permissions::from_options(
+                &permissions_options,
+            ));
+            state.put(deno_runtime::ops::UnstableChecker { unstable: true });
+            Ok(())
+        })
+        .build();
+    // js_extensions: `,` separated optional extension groups, the rest are always loaded
+    // web,webgpu,ffi,fs,net,process
+    let extension_groups = options.get("js_extensions", "all");
+    let extension_groups: Vec<&str> = extension_groups.split(',').map(|g| g.trim()).collect();
+    let enabled = |group: &str| extension_groups.contains(&"all") || extension_groups.contains(&group);
+
+    let mut extensions = extensions::deno_extensions(&enabled);
+    // the js side of every extension is in the snapshot, a missing group only fails when its ops are called.
+    // deno_core looks ops up by name when they are called and bootstrap.mainRuntime only calls ops of the
+    // always loaded extensions, so one snapshot serves every js_extensions set (startup_bench checks `none`)
+    extensions.push(mod_skynet::init());
+    extensions.push(mod_tls::init());
+    extensions.push(mod_inspector::init());
+    // Permissions ext (worker specific state)
+    extensions.push(perm_ext);
+
+    // js_heap_limit: max heap size in MB, 0 uses the v8 default
+    let heap_limit = options.get_usize("js_heap_limit", 0);
+    let create_params = if heap_limit > 0 {
+        Some(v8::CreateParams::default().heap_limits(0, heap_limit * 1024 * 1024))
+    } else {
+        None
+    };
+
+    // js_import_map: import map json file, applied before the jslib search paths.
+    // a broken map fails every import of the service with its error
+    let import_map = match options.get("js_import_map", "").as_str() {
+        "" => Ok(None),
+        path => import_map::ImportMap::load(path)
+            .map(Some)
+            .map_err(|err| err.to_string()),
+    };
+
+    // filled by the loader, read by op_apply_source_map and for uncaught errors
+    let source_maps: loader::SharedSourceMaps = Default::default();
+    let error_source_maps = source_maps.clone();
+
+    let mut runtime = Box::new(deno_core::JsRuntime::new(deno_core::RuntimeOptions {
+        extensions: extensions,
+        startup_snapshot: Some(js::skynet_isolate_init()),
+        module_loader: Some(std::rc::Rc::new(loader::ModuleLoader::new(source_maps.clone(), import_map))),
+        js_error_create_fn: Some(std::rc::Rc::new(move |js_error| {
+            loader::apply_source_map(&error_source_maps, js_error)
+        })),
+        create_params: create_params,
+        ..Default::default()
+    }));
+    runtime.op_state().borrow_mut().put(source_maps);
+
+    runtime
+}
+
+impl<'a> snjs<'a> {
+    fn runtime(&mut self) -> &mut deno_core::JsRuntime {
+        self.runtime.as_mut().unwrap()
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn snjs_release(ctx: *mut snjs<'static>) {
-    let ctx = unsafe { Box::from_raw(ctx) };
+    let skynet = unsafe { &*ctx }.skynet;
+    ffi_guard(skynet, || release_impl(ctx));
+}
+
+fn release_impl(ctx: *mut snjs<'static>) {
+    let mut ctx = unsafe { Box::from_raw(ctx) };
+    *ctx.alive.lock().unwrap() = false;
+    if ctx.runtime.is_some() && ctx.context != ptr::null_mut() {
+        shutdown_runtime(&mut ctx);
+    }
 
     unsafe { libc::free(ctx.custom_archive) };
     if ctx.waker != ptr::null_mut() {
@@ -200,46 +233,206 @@ pub extern "C" fn snjs_release(ctx: *mut snjs<'static>) {
             Box::from_raw(ctx.context);
         };
     }
+    let dedicated_pool = if ctx.tokio_rt != ptr::null_mut() && unsafe { &*ctx.tokio_rt }.is_dedicated() {
+        Some(unsafe { Box::from_raw(ctx.tokio_rt) })
+    } else {
+        None
+    };
 
     drop(ctx);
+    if let Some(pool) = dedicated_pool {
+        (*pool).shutdown();
+    }
+}
+
+// unload event -> clear timers -> cancel calls -> close resources -> drain pending ops -> drop the isolate
+fn shutdown_runtime(ctx: &mut snjs) {
+    let skynet = ctx.skynet;
+    let report = |msg: String| report_error(skynet, &msg);
+    // js_shutdown_timeout: ms to wait for pending ops after unload
+    let timeout = get_env(skynet, "js_shutdown_timeout", "1000")
+        .parse::<u64>()
+        .unwrap_or(1000);
+
+    let _isolate_scope = v8::IsolateScope::new(ctx.runtime().v8_isolate());
+    let _locker = v8::Locker::new(ctx.runtime().v8_isolate(), std::ptr::null_mut());
+    let _auto_check = deno_core::IsolateAutoCheck::new(ctx.runtime().v8_isolate());
+    let pool = unsafe { &*ctx.tokio_rt };
+    let _rt_guard = pool.enter();
+
+    // the service may be released while js is terminated
+    ctx.runtime().v8_isolate().cancel_terminate_execution();
+    if let Err(err) = ctx.runtime().execute_script(
+        "skynet_ts:unload.js",
+        "window.dispatchEvent(new Event('unload'));\nSkynet.clear_timers();",
+    ) {
+        report(format!("SkynetTs unload event error {:?}", err));
+    }
+
+    mod_skynet::cancel_calls(unsafe { &mut *ctx.context });
+
+    {
+        let op_state = ctx.runtime().op_state();
+        let mut op_state = op_state.borrow_mut();
+        let resources: Vec<deno_core::ResourceId> =
+            op_state.resource_table.names().map(|(rid, _)| rid).collect();
+        for rid in resources {
+            op_state.resource_table.close(rid);
+        }
+    }
+
+    let runtime = ctx.runtime.as_mut().unwrap();
+    let drain = pool.block_on(tokio::time::timeout(
+        std::time::Duration::from_millis(timeout),
+        runtime.run_event_loop(false),
+    ));
+    match drain {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => report(format!("SkynetTs Uncaught {:?}", err)),
+        Err(_) => {
+            let mut pending = String::new();
+            let op_state = runtime.op_state();
+            let op_state = op_state.borrow();
+            for (rid, name) in op_state.resource_table.names() {
+                pending += &format!("\n\tresource {} {}", rid, name);
+            }
+            for (name, metrics) in &op_state.borrow::<deno_runtime::metrics::RuntimeMetrics>().ops {
+                let count = metrics.ops_dispatched_async - metrics.ops_completed_async;
+                if count > 0 {
+                    pending += &format!("\n\top {} pending:{}", name, count);
+                }
+            }
+            report(format!(
+                "SkynetTs shutdown timeout after {}ms, force drop pending ops{}",
+                timeout, pending
+            ))
+        }
+    }
 }
 
 #[no_mangle]
-pub extern "C" fn snjs_signal(_ctx: *mut snjs, _signal: c_int) {}
+pub extern "C" fn snjs_signal(ctx: *mut snjs, signal: c_int) {
+    let skynet = unsafe { &*ctx }.skynet;
+    ffi_guard(skynet, || signal_impl(ctx, signal));
+}
+
+fn signal_impl(ctx: *mut snjs, signal: c_int) {
+    let ctx = unsafe { &*ctx };
+    report_error(ctx.skynet, &format!("recv a signal {}", signal));
+
+    if signal == 0 {
+        // only interrupt while js is running, otherwise the termination stays pending for the next message
+        let running = ctx.running.lock().unwrap();
+        if let Some(isolate_handle) = &ctx.isolate_handle {
+            if *running && !ctx.interrupted.swap(true, Ordering::SeqCst) {
+                isolate_handle.terminate_execution();
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ASYNC_PTYPE: c_int = read_async_ptype();
+}
+
+// js_async_ptype: message type of the deno async wakeup, read once for the process
+fn read_async_ptype() -> c_int {
+    let value = get_env(ptr::null(), "js_async_ptype", "");
+    if value.is_empty() {
+        return interface::PTYPE_DENO_ASYNC;
+    }
+    match value.parse::<c_int>() {
+        Ok(ptype) if ptype > interface::PTYPE_RESERVED_MAX && ptype <= interface::PTYPE_MAX => ptype,
+        _ => {
+            report_error(
+                ptr::null(),
+                &format!(
+                    "SkynetTs invalid js_async_ptype {}, expect {}..{}, use {}",
+                    value,
+                    interface::PTYPE_RESERVED_MAX + 1,
+                    interface::PTYPE_MAX,
+                    interface::PTYPE_DENO_ASYNC
+                ),
+            );
+            interface::PTYPE_DENO_ASYNC
+        }
+    }
+}
+
+pub fn async_ptype() -> c_int {
+    *ASYNC_PTYPE
+}
 
 fn get_env(ctx: *const c_void, name: &str, default: &str) -> String {
+    let name = match std::ffi::CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return String::from(default),
+    };
     unsafe {
         let cmd = std::ffi::CString::new("GETENV").unwrap();
-        let name = std::ffi::CString::new(name).unwrap();
         let result = interface::skynet_command(ctx, cmd.as_ptr(), name.as_ptr());
         if result == ptr::null() {
             String::from(default)
         } else {
-            String::from(CStr::from_ptr(result).to_str().unwrap())
+            CStr::from_ptr(result).to_string_lossy().into_owned()
         }
     }
 }
 
+thread_local! {
+    static CURRENT_SKYNET: std::cell::Cell<*const c_void> = std::cell::Cell::new(ptr::null());
+}
+
+// skynet_error with a message from js or rust, a NUL byte must not panic
+pub fn report_error(skynet: *const c_void, msg: &str) {
+    let msg = std::ffi::CString::new(msg.replace('\0', "\\0")).unwrap();
+    unsafe { interface::skynet_error(skynet, msg.as_ptr()) };
+}
+
+fn install_panic_hook() {
+    static PANIC_HOOK: std::sync::Once = std::sync::Once::new();
+    PANIC_HOOK.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            let skynet = CURRENT_SKYNET.with(|current| current.get());
+            let backtrace = backtrace::Backtrace::new();
+            report_error(skynet, &format!("SkynetTs panic: {}\n{:?}", info, backtrace));
+        }));
+    });
+}
+
+// a panic must not unwind into skynet, report it as the current service and return None
+fn ffi_guard<R>(skynet: *const c_void, f: impl FnOnce() -> R) -> Option<R> {
+    let prev = CURRENT_SKYNET.with(|current| current.replace(skynet));
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).ok();
+    CURRENT_SKYNET.with(|current| current.set(prev));
+    r
+}
+
 #[no_mangle]
 pub extern "C" fn dispatch_th_cb(
-    _skynet: *const c_void,
+    skynet: *const c_void,
     ctx: *mut snjs,
     stype: c_int,
     n: c_int,
 ) -> c_int {
+    ffi_guard(skynet, || dispatch_th_impl(ctx, stype, n)).unwrap_or(0)
+}
+
+fn dispatch_th_impl(ctx: *mut snjs, stype: c_int, n: c_int) -> c_int {
     //println!("======= dispatch_th_cb {} {}", stype, n);
     let ctx = unsafe { &mut *ctx };
 
     if stype == 0 {
-        unsafe { ctx.runtime.v8_isolate().enter() };
+        unsafe { ctx.runtime().v8_isolate().enter() };
+        let archive = if ctx.inspector_session_len > 0 {
+            // ctx.custom_archive
+            std::ptr::null_mut()
+        } else {
+            std::ptr::null_mut()
+        };
         ctx.locker = Box::into_raw(Box::new(v8::Locker::new(
-            ctx.runtime.v8_isolate(),
-            if ctx.inspector_session_len > 0 {
-                // ctx.custom_archive
-                std::ptr::null_mut()
-            } else {
-                std::ptr::null_mut()
-            },
+            ctx.runtime().v8_isolate(),
+            archive,
         )));
 
         let rt = unsafe { &mut *ctx.tokio_rt };
@@ -250,12 +443,12 @@ pub extern "C" fn dispatch_th_cb(
             let _r = Box::from_raw(ctx.tokio_guard);
             ctx.tokio_guard = ptr::null_mut();
         };
-        v8::HandleScope::new(ctx.runtime.v8_isolate());
+        v8::HandleScope::new(ctx.runtime().v8_isolate());
         unsafe {
             Box::from_raw(ctx.locker);
             ctx.locker = ptr::null_mut();
         };
-        unsafe { ctx.runtime.v8_isolate().exit() };
+        unsafe { ctx.runtime().v8_isolate().exit() };
     }
 
     0
@@ -269,38 +462,113 @@ fn dispatch_impl(
     msg: *const c_void,
     sz: size_t,
     remainder_message: i32,
-) {
+) -> bool {
+    *ctx.running.lock().unwrap() = true;
+    let reserve = dispatch_message(ctx, stype, session, source, msg, sz, remainder_message);
+
+    let interrupted = {
+        let mut running = ctx.running.lock().unwrap();
+        *running = false;
+        ctx.interrupted.swap(false, Ordering::SeqCst)
+    };
+    if interrupted {
+        ctx.runtime().v8_isolate().cancel_terminate_execution();
+        report_error(
+            ctx.skynet,
+            &format!(
+                "SkynetTs message interrupted type:{} session:{} source:{:08x}",
+                stype & 0xffff,
+                session,
+                source
+            ),
+        );
+    }
+
+    if ctx.heap_exceeded.swap(false, Ordering::SeqCst) {
+        ctx.runtime().v8_isolate().cancel_terminate_execution();
+        heap_exceeded_exit(ctx);
+    }
+    reserve
+}
+
+fn heap_exceeded_exit(ctx: &mut snjs) {
+    let mut stats = v8::HeapStatistics::default();
+    ctx.runtime().v8_isolate().get_heap_statistics(&mut stats);
+    report_error(
+        ctx.skynet,
+        &format!(
+            "SkynetTs heap limit exceeded, exit service. total:{}K used:{}K limit:{}K external:{}K",
+            stats.total_heap_size() / 1024,
+            stats.used_heap_size() / 1024,
+            stats.heap_size_limit() / 1024,
+            stats.external_memory() / 1024,
+        ),
+    );
+
+    // same as snlua report_launcher_error
+    let launcher = std::ffi::CString::new(".launcher").unwrap();
+    let error = "ERROR";
+    unsafe {
+        interface::skynet_sendname(
+            ctx.skynet,
+            0,
+            launcher.as_ptr(),
+            interface::PTYPE_TEXT,
+            0,
+            error.as_ptr() as *const c_void,
+            error.len(),
+        )
+    };
+
+    let cmd = std::ffi::CString::new("EXIT").unwrap();
+    unsafe { interface::skynet_command(ctx.skynet, cmd.as_ptr(), ptr::null()) };
+}
+
+fn dispatch_message(
+    ctx: &mut snjs,
+    stype: c_int,
+    session: c_int,
+    source: c_int,
+    msg: *const c_void,
+    sz: size_t,
+    remainder_message: i32,
+) -> bool {
     let raw_type = stype & 0xffff;
-    if raw_type == interface::PTYPE_DENO_ASYNC {
+    if raw_type == async_ptype() {
+        // clear before polling, so a wake from inside the poll queues the next message
+        ctx.wake_pending.store(false, Ordering::Release);
         let poll_result = ctx
-            .runtime
+            .runtime()
             .poll_event_loop(unsafe { &mut *ctx.waker_context }, false);
         if let std::task::Poll::Ready(Err(err)) = poll_result {
-            let err_msg = std::ffi::CString::new(format!(
-                "SkynetTs Uncaught {:?}",
-                err
-            ))
-            .unwrap();
-            unsafe { interface::skynet_error(ctx.skynet, err_msg.as_ptr()) };
+            report_error(ctx.skynet, &format!("SkynetTs Uncaught {:?}", err));
         }
+        false
     } else {
-        mod_skynet::dispatch(ctx, raw_type, session, source, msg as *const u8, sz, remainder_message);
+        let reserve = mod_skynet::dispatch(ctx, raw_type, session, source, msg as *const u8, sz, remainder_message);
 
-        let promise_exception = ctx.runtime.check_promise_exceptions();
+        let promise_exception = ctx.runtime().check_promise_exceptions();
         if let Err(err) = promise_exception {
-            let err_msg = std::ffi::CString::new(format!(
-                "SkynetTs Uncaught {:?}",
-                err
-            ))
-            .unwrap();
-            unsafe { interface::skynet_error(ctx.skynet, err_msg.as_ptr()) };
+            report_error(ctx.skynet, &format!("SkynetTs Uncaught {:?}", err));
         }
+        reserve
     }
 }
 
 #[no_mangle]
 pub extern "C" fn dispatch_cb(
-    _skynet: *const c_void,
+    skynet: *const c_void,
+    ctx: *mut snjs,
+    stype: c_int,
+    session: c_int,
+    source: c_int,
+    msg: *const c_void,
+    sz: size_t,
+) -> c_int {
+    ffi_guard(skynet, || dispatch_cb_impl(ctx, stype, session, source, msg, sz)).unwrap_or(0)
+}
+
+fn dispatch_cb_impl(
     ctx: *mut snjs,
     stype: c_int,
     session: c_int,
@@ -312,27 +580,25 @@ pub extern "C" fn dispatch_cb(
     // println!("======= dispatch_cb {} {}", stype, sz);
 
     if stype & 0x40000 == 0 {
-        let _isolate_scope = v8::IsolateScope::new(ctx.runtime.v8_isolate());
-        let _locker = v8::Locker::new(
-            ctx.runtime.v8_isolate(),
-            if ctx.inspector_session_len > 0 {
-                // ctx.custom_archive
-                std::ptr::null_mut()
-            } else {
-                std::ptr::null_mut()
-            },
-        );
-        let _auto_check = deno_core::IsolateAutoCheck::new(ctx.runtime.v8_isolate());
+        let archive = if ctx.inspector_session_len > 0 {
+            // ctx.custom_archive
+            std::ptr::null_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        let _isolate_scope = v8::IsolateScope::new(ctx.runtime().v8_isolate());
+        let _locker = v8::Locker::new(ctx.runtime().v8_isolate(), archive);
+        let _auto_check = deno_core::IsolateAutoCheck::new(ctx.runtime().v8_isolate());
         let rt = unsafe { &mut *ctx.tokio_rt };
         let _rt_guard = rt.enter();
 
-        dispatch_impl(ctx, stype, session, source, msg, sz, 0);
+        let reserve = dispatch_impl(ctx, stype, session, source, msg, sz, 0);
+        reserve as c_int
     } else {
         ctx.remainder_message = ctx.remainder_message - 1;
-        dispatch_impl(ctx, stype, session, source, msg, sz, ctx.remainder_message);
+        let remainder_message = ctx.remainder_message;
+        dispatch_impl(ctx, stype, session, source, msg, sz, remainder_message) as c_int
     }
-
-    0
 }
 
 #[no_mangle]
@@ -345,24 +611,38 @@ pub extern "C" fn init_cb(
     msg: *const c_void,
     _sz: size_t,
 ) -> c_int {
+    match ffi_guard(skynet, || init_cb_impl(skynet, ctx_ptr, msg)) {
+        Some(r) => r,
+        None => {
+            let cmd = std::ffi::CString::new("EXIT").unwrap();
+            unsafe { interface::skynet_command(skynet, cmd.as_ptr(), ptr::null()) };
+            0
+        }
+    }
+}
+
+fn init_cb_impl(skynet: *const c_void, ctx_ptr: *mut snjs, msg: *const c_void) -> c_int {
     let ctx = unsafe { &mut *ctx_ptr };
     unsafe { interface::skynet_callback(ctx.skynet, ptr::null_mut(), ptr::null_mut()) };
 
-    let args = unsafe { CStr::from_ptr(msg as *const c_char) }
-        .to_str()
-        .unwrap();
+    // split like skynet lua loader: string.gmatch(args, "%S+")
+    let args: Vec<String> = unsafe { CStr::from_ptr(msg as *const c_char) }
+        .to_string_lossy()
+        .split_whitespace()
+        .map(|arg| arg.to_owned())
+        .collect();
     let loader_path = get_env(ctx.skynet, "js_loader", "./js/lib/loader.js");
 
-    let _isolate_scope = v8::IsolateScope::new(ctx.runtime.v8_isolate());
-    let _locker = v8::Locker::new(ctx.runtime.v8_isolate(), std::ptr::null_mut());
-    let _auto_check = deno_core::IsolateAutoCheck::new(ctx.runtime.v8_isolate());
+    let _isolate_scope = v8::IsolateScope::new(ctx.runtime().v8_isolate());
+    let _locker = v8::Locker::new(ctx.runtime().v8_isolate(), std::ptr::null_mut());
+    let _auto_check = deno_core::IsolateAutoCheck::new(ctx.runtime().v8_isolate());
     let rt = unsafe { &mut *ctx.tokio_rt };
     let _rt_guard = rt.enter();
 
     {
         let runtime_options = deno_core::serde_json::json!({
             "args": args,
-            "applySourceMaps": false,
+            "applySourceMaps": true,
             "debugFlag": true,
             "denoVersion": "",
             "noColor": false,
@@ -376,15 +656,15 @@ pub extern "C" fn init_cb(
         });
 
         let script = format!(
-            "bootstrap.mainRuntime({})",
+            "bootstrap.mainRuntime({});\nSkynet.hook_timers();",
             serde_json::to_string_pretty(&runtime_options).unwrap()
         );
-        let _r = ctx.runtime.execute_script("", &script);
+        let _r = ctx.runtime().execute_script("", &script);
 
         let inspector = if get_env(ctx.skynet, "js_inspector", "false") == "true" {
-            let global = ctx.runtime.global_context();
+            let global = ctx.runtime().global_context();
             Some(mod_inspector::Inspector::new(
-                &mut ctx.runtime.handle_scope(),
+                &mut ctx.runtime().handle_scope(),
                 global,
             ))
         } else {
@@ -393,6 +673,7 @@ pub extern "C" fn init_cb(
 
         let data = Box::into_raw(Box::new(ContextData {
             skynet: ctx.skynet,
+            handle: ctx.skynet_handle,
             ctx: ctx_ptr as *const libc::c_void,
             bs: None,
             cb: None,
@@ -401,8 +682,28 @@ pub extern "C" fn init_cb(
             bs_offset: 0,
             bs_flag: 0,
             bs_temp: None,
+            bs_messages: Vec::new(),
+            bs_external: Vec::new(),
+            // js_zero_copy_size: messages from this size on skip the copy into the batch buffer, 0 disables
+            zero_copy_size: get_env(ctx.skynet, "js_zero_copy_size", "65536")
+                .parse()
+                .unwrap_or_else(|_| {
+                    report_error(ctx.skynet, "SkynetTs invalid js_zero_copy_size, use 65536");
+                    65536
+                }),
+            // js_batch_flush_size: call js once the batch buffer reaches this size
+            bs_flush_size: get_env(ctx.skynet, "js_batch_flush_size", "1048576")
+                .parse()
+                .unwrap_or(1024 * 1024),
+            calls: std::collections::HashMap::new(),
+            timed_out: std::collections::HashMap::new(),
+            timers: std::collections::HashMap::new(),
+            streams: std::collections::HashMap::new(),
+            resolve_cache: std::collections::HashMap::new(),
+            init_args: args,
+            loader_path: loader_path.clone(),
         }));
-        ctx.runtime
+        ctx.runtime()
             .op_state()
             .borrow_mut()
             .put::<SkynetContext>(data);
@@ -411,19 +712,25 @@ pub extern "C" fn init_cb(
 
     // register JsRuntimeState.waker
     let _r = ctx
-        .runtime
+        .runtime()
         .poll_event_loop(unsafe { &mut *ctx.waker_context }, false);
-    let base_path = url::Url::from_file_path(std::env::current_dir().unwrap())
-        .unwrap()
-        .to_string()
-        + "/js_loader.js";
-    let loader_script = String::from("")
-        + r#"
+    let base_path = match std::env::current_dir()
+        .ok()
+        .and_then(|dir| url::Url::from_file_path(dir).ok())
+    {
+        Some(url) => url.to_string() + "/js_loader.js",
+        None => {
+            report_error(skynet, "can not loader, invalid current directory");
+
+            let cmd = std::ffi::CString::new("EXIT").unwrap();
+            unsafe { interface::skynet_command(skynet, cmd.as_ptr(), ptr::null()) };
+            return 0;
+        }
+    };
+    let loader_script = r#"
 "use strict";
 (async (window) => {
-    let loader_path = '"#
-        + &loader_path
-        + r#"';
+    let loader_path = Skynet.loader_path();
     try {
         await import(loader_path);
     } catch (e) {
@@ -432,16 +739,10 @@ pub extern "C" fn init_cb(
     }
 })(this);
     "#;
-    let _r = ctx
-        .runtime
-        .execute_script(&base_path, &format!("JS_INIT_ARGS='{}'", args));
 
-    let r = ctx.runtime.execute_script(&base_path, &loader_script);
+    let r = ctx.runtime().execute_script(&base_path, loader_script);
     if let Err(err) = r {
-        let err_msg =
-            std::ffi::CString::new(format!("can not loader {:?} err:{:?}", loader_path, err))
-                .unwrap();
-        unsafe { interface::skynet_error(skynet, err_msg.as_ptr()) };
+        report_error(skynet, &format!("can not loader {:?} err:{:?}", loader_path, err));
 
         let cmd = std::ffi::CString::new("EXIT").unwrap();
         unsafe { interface::skynet_command(skynet, cmd.as_ptr(), ptr::null()) };
@@ -461,8 +762,46 @@ pub extern "C" fn init_cb(
 
 #[no_mangle]
 pub extern "C" fn snjs_init(ptr: *mut snjs, skynet: *const c_void, args: *const c_char) -> c_int {
+    ffi_guard(skynet, || init_impl(ptr, skynet, args)).unwrap_or(1)
+}
+
+fn init_impl(ptr: *mut snjs, skynet: *const c_void, args: *const c_char) -> c_int {
     let ctx = unsafe { &mut *ptr };
     ctx.skynet = skynet;
+
+    let args = unsafe { CStr::from_ptr(args) }.to_string_lossy();
+    let (options, args) = options::ServiceOptions::parse(skynet, &args);
+    ctx.tokio_rt = tokio_pool::get(&options);
+    ctx.runtime = Some(create_runtime(&options));
+    ctx.isolate_handle = Some(ctx.runtime().v8_isolate().thread_safe_handle());
+    {
+        let _isolate_scope = v8::IsolateScope::new(ctx.runtime().v8_isolate());
+        let _locker = v8::Locker::new(ctx.runtime().v8_isolate(), std::ptr::null_mut());
+
+        let heap_exceeded = ctx.heap_exceeded.clone();
+        let isolate_handle = ctx.runtime().v8_isolate().thread_safe_handle();
+        ctx.runtime()
+            .add_near_heap_limit_callback(move |current_limit, initial_limit| {
+                // v8 may call again while the terminated script unwinds, the limit is raised only once
+                if heap_exceeded.swap(true, Ordering::SeqCst) {
+                    return current_limit;
+                }
+                report_error(
+                    skynet,
+                    &format!(
+                        "SkynetTs near heap limit current:{}K initial:{}K",
+                        current_limit / 1024,
+                        initial_limit / 1024
+                    ),
+                );
+
+                // stop the script, dispatch_impl exits the service after the current message
+                isolate_handle.terminate_execution();
+                // give v8 room to unwind instead of aborting the process
+                initial_limit + initial_limit / 4
+            });
+    }
+
     unsafe {
         interface::skynet_callback(
             skynet,
@@ -472,10 +811,12 @@ pub extern "C" fn snjs_init(ptr: *mut snjs, skynet: *const c_void, args: *const
     };
 
     let (init_msg, init_sz) = unsafe {
-        let sz = libc::strlen(args) + 1;
+        let args = std::ffi::CString::new(args).unwrap();
+        let args = args.as_bytes_with_nul();
+        let sz = args.len();
         //let msg = libc::malloc(sz);
-        let msg = interface::skynet_malloc(sz as u32);
-        libc::memcpy(msg, args as *const c_void, sz);
+        let msg = interface::skynet_malloc(sz);
+        libc::memcpy(msg, args.as_ptr() as *const c_void, sz);
         (msg, sz)
     };
     let handle_id = unsafe {
@@ -489,18 +830,36 @@ pub extern "C" fn snjs_init(ptr: *mut snjs, skynet: *const c_void, args: *const
     unsafe impl Send for SharedWaker {}
     unsafe impl Sync for SharedWaker {}
     let shared = SharedWaker(skynet.clone());
+    let alive = ctx.alive.clone();
+    let async_ptype = async_ptype();
+    let wake_pending = ctx.wake_pending.clone();
+    let wake_stats = ctx.wake_stats.clone();
     let waker = Box::new(async_task::waker_fn(move || {
         // println!("-=============== waker {:?}", shared.0);
-        unsafe {
+        // tokio tasks may still wake after snjs_release, the skynet context is gone by then
+        let alive = alive.lock().unwrap();
+        if !*alive {
+            return;
+        }
+        // the queued wake message polls the whole event loop anyway
+        if wake_pending.swap(true, Ordering::AcqRel) {
+            wake_stats.suppressed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        wake_stats.sent.fetch_add(1, Ordering::Relaxed);
+        let r = unsafe {
             interface::skynet_send(
                 shared.0,
                 0,
                 handle_id,
-                interface::PTYPE_TAG_DONTCOPY | interface::PTYPE_DENO_ASYNC,
+                interface::PTYPE_TAG_DONTCOPY | async_ptype,
                 0,
                 ptr::null(),
                 0,
-            );
+            )
+        };
+        if r < 0 {
+            wake_pending.store(false, Ordering::Release);
         }
     }));
     ctx.waker = Box::into_raw(waker);
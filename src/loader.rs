@@ -1,11 +1,433 @@
+use deno_ast::swc::codegen::text_writer::JsWriter;
+use deno_ast::swc::codegen::Node;
+use deno_ast::swc::common::{chain, FileName, Globals, SourceMap, GLOBALS};
+use deno_ast::swc::transforms::pass::Optional;
+use deno_ast::swc::transforms::{fixer, helpers, hygiene, proposals, react, typescript};
+use deno_ast::swc::visit::FoldWith;
+use deno_ast::MediaType;
 use deno_core::futures::FutureExt;
+use crate::import_map::ImportMap;
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::pin::Pin;
 use std::rc::Rc;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
+lazy_static::lazy_static! {
+    // transpiled typescript shared by every service, keyed by module url and source hash
+    static ref TRANSPILED: Mutex<HashMap<(String, u64), Arc<Emitted>>> = Mutex::new(HashMap::new());
+}
+
+struct Emitted {
+    code: String,
+    source_map: Option<Arc<Vec<u8>>>,
+}
+
+fn media_type(path: &Path) -> MediaType {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ts") | Some("mts") => MediaType::TypeScript,
+        Some("tsx") => MediaType::Tsx,
+        _ => MediaType::JavaScript,
+    }
+}
+
+fn source_hash(media_type: MediaType, code: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    media_type.hash(&mut hasher);
+    code.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn transpile(
+    module_specifier: &deno_core::ModuleSpecifier,
+    media_type: MediaType,
+    code: String,
+) -> Result<Arc<Emitted>, deno_core::error::AnyError> {
+    let hash = source_hash(media_type, &code);
+    let key = (module_specifier.to_string(), hash);
+    if let Some(emitted) = TRANSPILED.lock().unwrap().get(&key) {
+        return Ok(emitted.clone());
+    }
+
+    let parsed = deno_ast::parse_module(deno_ast::ParseParams {
+        specifier: module_specifier.to_string(),
+        source: deno_ast::SourceTextInfo::from_string(code),
+        media_type,
+        capture_tokens: false,
+        maybe_syntax: None,
+    })
+    .map_err(|err| deno_core::error::generic_error(err.to_string()))?;
+    let (code, source_map) = emit(module_specifier, &parsed, media_type == MediaType::Tsx)
+        .map_err(|err| deno_core::error::generic_error(format!("{}: {}", module_specifier, err)))?;
+
+    let emitted = Arc::new(Emitted {
+        code,
+        source_map: Some(Arc::new(source_map)),
+    });
+    TRANSPILED.lock().unwrap().insert(key, emitted.clone());
+    Ok(emitted)
+}
+
+// deno_ast 0.1 only parses, these are the passes deno 1.13 runs on a module it transpiles.
+// the map is kept apart from the code, see SourceMaps
+fn emit(
+    module_specifier: &deno_core::ModuleSpecifier,
+    parsed: &deno_ast::ParsedSource,
+    transform_jsx: bool,
+) -> Result<(String, Vec<u8>), deno_core::error::AnyError> {
+    let program = (*parsed.program()).clone();
+    let source_map = Rc::new(SourceMap::default());
+    source_map.new_source_file(
+        FileName::Url(module_specifier.clone()),
+        parsed.source().text().to_string(),
+    );
+    let comments = parsed.comments().as_single_threaded();
+
+    let jsx_pass = react::react(
+        source_map.clone(),
+        Some(&comments),
+        react::Options {
+            use_builtins: true,
+            ..Default::default()
+        },
+    );
+    let mut passes = chain!(
+        Optional::new(jsx_pass, transform_jsx),
+        proposals::decorators::decorators(proposals::decorators::Config {
+            legacy: true,
+            emit_metadata: false,
+        }),
+        helpers::inject_helpers(),
+        typescript::strip::strip_with_config(typescript::strip::Config {
+            use_define_for_class_fields: true,
+            no_empty_export: true,
+            ..Default::default()
+        }),
+        fixer(Some(&comments)),
+        hygiene(),
+    );
+    let program = GLOBALS.set(&Globals::new(), || {
+        helpers::HELPERS.set(&helpers::Helpers::new(false), || program.fold_with(&mut passes))
+    });
+
+    let mut code = Vec::new();
+    let mut mappings = Vec::new();
+    {
+        let mut emitter = deno_ast::swc::codegen::Emitter {
+            cfg: deno_ast::swc::codegen::Config { minify: false },
+            comments: Some(&comments),
+            cm: source_map.clone(),
+            wr: Box::new(JsWriter::new(source_map.clone(), "\n", &mut code, Some(&mut mappings))),
+        };
+        program.emit_with(&mut emitter)?;
+    }
+    let mut map = source_map.build_source_map_from(&mut mappings, None);
+    // error frames show the typescript line, see SourceMaps::original_position
+    for idx in 0..map.get_source_count() {
+        map.set_source_contents(idx, Some(parsed.source().text_str()));
+    }
+    let mut buf = Vec::new();
+    map.to_writer(&mut buf)?;
+    Ok((String::from_utf8(code)?, buf))
+}
+
+const EXTENSIONS: [&str; 3] = ["js", "mjs", "ts"];
+const CONDITIONS: [&str; 4] = ["deno", "import", "module", "default"];
+
+fn with_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+// the file itself, then .js/.mjs/.ts appended, then a directory's package.json and index.*
+fn probe(path: &Path, packages: bool) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    for ext in EXTENSIONS.iter() {
+        let file = with_extension(path, ext);
+        if file.is_file() {
+            return Some(file);
+        }
+    }
+    if path.is_dir() {
+        if packages {
+            if let Some(entry) = package_entry(path, ".") {
+                return Some(entry);
+            }
+        }
+        for ext in EXTENSIONS.iter() {
+            let index = path.join(format!("index.{}", ext));
+            if index.is_file() {
+                return Some(index);
+            }
+        }
+    }
+    None
+}
+
+// "@scope/name/sub" -> ("@scope/name", "./sub"), "name" -> ("name", "."). None for relative or url specifiers
+fn split_package(specifier: &str) -> Option<(&str, String)> {
+    if specifier.starts_with('.') || specifier.starts_with('/') || specifier.contains(':') {
+        return None;
+    }
+    let mut end = specifier.find('/').unwrap_or_else(|| specifier.len());
+    if specifier.starts_with('@') && end < specifier.len() {
+        end = specifier[end + 1..]
+            .find('/')
+            .map(|pos| end + 1 + pos)
+            .unwrap_or_else(|| specifier.len());
+    }
+    let subpath = format!(".{}", &specifier[end..]);
+    Some((&specifier[..end], subpath))
+}
+
+fn condition_target(target: &serde_json::Value) -> Option<String> {
+    match target {
+        serde_json::Value::String(target) => Some(target.clone()),
+        serde_json::Value::Array(targets) => targets.iter().find_map(condition_target),
+        serde_json::Value::Object(conditions) => conditions
+            .iter()
+            .filter(|(condition, _)| CONDITIONS.contains(&condition.as_str()))
+            .find_map(|(_, target)| condition_target(target)),
+        _ => None,
+    }
+}
+
+// package.json `exports`: a single target, conditions, or subpaths with an optional `*`
+fn exports_target(exports: &serde_json::Value, subpath: &str) -> Option<String> {
+    let subpaths = match exports.as_object() {
+        Some(map) if !map.is_empty() && map.keys().all(|key| key.starts_with('.')) => map,
+        _ if subpath == "." => return condition_target(exports),
+        _ => return None,
+    };
+    if let Some(target) = subpaths.get(subpath) {
+        return condition_target(target);
+    }
+    for (key, target) in subpaths {
+        if let Some(star) = key.find('*') {
+            let (prefix, suffix) = (&key[..star], &key[star + 1..]);
+            if subpath.len() >= prefix.len() + suffix.len()
+                && subpath.starts_with(prefix)
+                && subpath.ends_with(suffix)
+            {
+                let matched = &subpath[prefix.len()..subpath.len() - suffix.len()];
+                return condition_target(target).map(|target| target.replace('*', matched));
+            }
+        }
+    }
+    None
+}
+
+// the file a package directory gives for subpath through `exports`, or `main` for the package itself
+fn package_entry(dir: &Path, subpath: &str) -> Option<PathBuf> {
+    let json = std::fs::read_to_string(dir.join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&json).ok()?;
+    let target = match package.get("exports") {
+        Some(exports) => exports_target(exports, subpath)?,
+        None if subpath == "." => package.get("main")?.as_str()?.to_string(),
+        None => subpath.to_string(),
+    };
+    probe(&dir.join(target), false)
+}
+
+fn file_url(path: &Path) -> Option<deno_core::ModuleSpecifier> {
+    deno_core::ModuleSpecifier::from_file_path(std::env::current_dir().ok()?.join(path)).ok()
+}
+
+// a file url that doesn't name a file is probed like a search path
+fn probe_url(url: deno_core::ModuleSpecifier) -> deno_core::ModuleSpecifier {
+    if url.scheme() != "file" {
+        return url;
+    }
+    let path = match url.to_file_path() {
+        Ok(path) if !path.is_file() => probe(&path, true),
+        _ => None,
+    };
+    path.and_then(|path| file_url(&path)).unwrap_or(url)
+}
+
+fn search_module(
+    search_paths: &[String],
+    specifier: &str,
+    tried: &mut Vec<String>,
+) -> Option<deno_core::ModuleSpecifier> {
+    let package = split_package(specifier);
+    for search_path in search_paths {
+        let candidate = search_path.replace("?", specifier);
+        if let Some(path) = probe(Path::new(&candidate), true) {
+            return file_url(&path);
+        }
+        tried.push(candidate);
+
+        // a vendored package under the directory part of the pattern
+        if let (Some(pos), Some((name, subpath))) = (search_path.find('?'), package.as_ref()) {
+            let dir = PathBuf::from(format!("{}{}", &search_path[..pos], name));
+            if let Some(path) = package_entry(&dir, subpath) {
+                return file_url(&path);
+            }
+            tried.push(format!("{}/package.json {}", dir.display(), subpath));
+        }
+    }
+    None
+}
+
+// the map of a plain js module: an inline data url, the file named by sourceMappingURL or a sibling .js.map
+fn js_source_map(path: &Path, code: &str) -> Option<Arc<Vec<u8>>> {
+    const DATA_URL: &str = "data:application/json;base64,";
+    let url = code
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| line.trim().strip_prefix("//# sourceMappingURL="));
+
+    let map = match url {
+        Some(url) if url.starts_with(DATA_URL) => base64::decode(&url[DATA_URL.len()..]).ok(),
+        Some(url) => std::fs::read(path.with_file_name(url)).ok(),
+        None => {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".map");
+            std::fs::read(sibling).ok()
+        }
+    };
+    map.map(Arc::new)
+}
+
+// source maps of the modules one service loaded, parsed on the first lookup
 #[derive(Default)]
-pub struct ModuleLoader();
+pub struct SourceMaps {
+    raw: HashMap<String, Arc<Vec<u8>>>,
+    parsed: HashMap<String, Option<Rc<sourcemap::SourceMap>>>,
+}
+
+pub type SharedSourceMaps = Rc<RefCell<SourceMaps>>;
+
+impl SourceMaps {
+    fn insert(&mut self, module_url: String, map: Arc<Vec<u8>>) {
+        self.parsed.remove(&module_url);
+        self.raw.insert(module_url, map);
+    }
+
+    fn get(&mut self, module_url: &str) -> Option<Rc<sourcemap::SourceMap>> {
+        if let Some(map) = self.parsed.get(module_url) {
+            return map.clone();
+        }
+        let map = self
+            .raw
+            .get(module_url)
+            .and_then(|raw| sourcemap::SourceMap::from_slice(raw).ok())
+            .map(Rc::new);
+        self.parsed.insert(module_url.to_string(), map.clone());
+        map
+    }
+
+    // 1-based line and column in the generated code -> file, line, column and line text in the source
+    pub fn original_position(
+        &mut self,
+        file_name: &str,
+        line_number: i64,
+        column_number: i64,
+    ) -> Option<(String, i64, i64, Option<String>)> {
+        if line_number < 1 || column_number < 1 {
+            return None;
+        }
+        let map = self.get(file_name)?;
+        let token = map.lookup_token(line_number as u32 - 1, column_number as u32 - 1)?;
+        let source = token.get_source()?;
+        let source_line = map
+            .get_source_contents(token.get_src_id())
+            .and_then(|contents| contents.lines().nth(token.get_src_line() as usize))
+            .map(|line| line.to_string());
+        Some((
+            source.to_string(),
+            token.get_src_line() as i64 + 1,
+            token.get_src_col() as i64 + 1,
+            source_line,
+        ))
+    }
+}
+
+// moves the position of an uncaught error into the source, the stack was already mapped by prepareStackTrace
+pub fn apply_source_map(
+    source_maps: &SharedSourceMaps,
+    mut js_error: deno_core::error::JsError,
+) -> deno_core::error::AnyError {
+    let position = match (&js_error.script_resource_name, js_error.line_number, js_error.start_column) {
+        (Some(file_name), Some(line_number), Some(start_column)) => source_maps
+            .borrow_mut()
+            .original_position(file_name, line_number, start_column + 1),
+        _ => None,
+    };
+    if let Some((file_name, line_number, column_number, source_line)) = position {
+        let start_column = column_number - 1;
+        js_error.end_column = js_error
+            .end_column
+            .map(|end_column| end_column - js_error.start_column.unwrap() + start_column);
+        js_error.script_resource_name = Some(file_name);
+        js_error.line_number = Some(line_number);
+        js_error.start_column = Some(start_column);
+        js_error.source_line = source_line;
+    }
+    js_error.into()
+}
+
+pub struct ModuleLoader {
+    source_maps: SharedSourceMaps,
+    import_map: Result<Option<ImportMap>, String>,
+}
+
+impl ModuleLoader {
+    pub fn new(source_maps: SharedSourceMaps, import_map: Result<Option<ImportMap>, String>) -> Self {
+        ModuleLoader {
+            source_maps,
+            import_map,
+        }
+    }
+}
+
+impl ModuleLoader {
+    fn resolve_uncached(
+        &self,
+        search_paths: &[String],
+        specifier: &str,
+        referrer: &str,
+    ) -> Result<deno_core::ModuleSpecifier, deno_core::error::AnyError> {
+        let import_map = self
+            .import_map
+            .as_ref()
+            .map_err(|err| deno_core::error::generic_error(format!("js_import_map: {}", err)))?;
+        if let Some(import_map) = import_map {
+            if let Some(r) = import_map.resolve(specifier, referrer)? {
+                return Ok(probe_url(r));
+            }
+        }
+
+        let mut tried = Vec::new();
+        if let Some(r) = search_module(search_paths, specifier, &mut tried) {
+            return Ok(r);
+        }
+
+        deno_core::resolve_import(specifier, referrer)
+            .map(probe_url)
+            .map_err(|err| {
+                let mut msg = format!("{}\n  from {}", err, referrer);
+                if import_map.is_some() {
+                    msg.push_str("\n  no match in js_import_map");
+                }
+                for candidate in &tried {
+                    msg.push_str(&format!("\n  tried {}", candidate));
+                }
+                deno_core::error::generic_error(msg)
+            })
+    }
+}
+
 impl deno_core::ModuleLoader for ModuleLoader {
     fn resolve(
         &self,
@@ -18,17 +440,14 @@ impl deno_core::ModuleLoader for ModuleLoader {
         let skynet = op_state_rc.borrow_mut::<crate::SkynetContext>();
         let skynet = unsafe { &mut **skynet };
 
-        for search_path in &skynet.module_search_paths {
-            let search_path = search_path.replace("?", specifier);
-            if Path::new(&search_path).is_file() {
-                let r = deno_core::ModuleSpecifier::from_file_path(std::env::current_dir().unwrap().join(search_path));
-                if let Ok(r) = r {
-                    return Ok(r);
-                }
-            }
+        // cleared when the search paths change
+        let key = (specifier.to_string(), referrer.to_string());
+        if let Some(r) = skynet.resolve_cache.get(&key) {
+            return Ok(r.clone());
         }
-
-        Ok(deno_core::resolve_import(specifier, referrer)?)
+        let r = self.resolve_uncached(&skynet.module_search_paths, specifier, referrer)?;
+        skynet.resolve_cache.insert(key, r.clone());
+        Ok(r)
     }
 
     fn load(
@@ -39,19 +458,32 @@ impl deno_core::ModuleLoader for ModuleLoader {
         _is_dynamic: bool,
     ) -> Pin<Box<deno_core::ModuleSourceFuture>> {
         let module_specifier = module_specifier.clone();
+        let source_maps = self.source_maps.clone();
         async move {
-            let mut path = module_specifier.to_file_path().map_err(|_| {
+            let path = module_specifier.to_file_path().map_err(|_| {
                 deno_core::error::generic_error(format!(
                     "Provided module specifier \"{}\" is not a file URL.",
                     module_specifier
                 ))
             })?;
 
-            if path.extension().is_none() {
-                path = path.with_extension("js");
+            let path = probe(&path, true).unwrap_or(path);
+            let media_type = media_type(&path);
+
+            let mut code = std::fs::read_to_string(&path)?;
+            let source_map = if media_type != MediaType::JavaScript {
+                let emitted = transpile(&module_specifier, media_type, code)?;
+                code = emitted.code.clone();
+                emitted.source_map.clone()
+            } else {
+                js_source_map(&path, &code)
+            };
+            if let Some(source_map) = source_map {
+                source_maps
+                    .borrow_mut()
+                    .insert(module_specifier.to_string(), source_map);
             }
 
-            let code = std::fs::read_to_string(path)?;
             let module = deno_core::ModuleSource {
                 code,
                 module_url_specified: module_specifier.to_string(),
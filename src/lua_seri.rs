@@ -0,0 +1,378 @@
+// lua-seri.c compatible serialization, the wire format of lua `skynet.pack`
+use byteorder::{ByteOrder, LittleEndian};
+use rusty_v8 as v8;
+use std::convert::TryFrom;
+
+const TYPE_NIL: u8 = 0;
+const TYPE_BOOLEAN: u8 = 1;
+// hibits 0 false 1 true
+const TYPE_NUMBER: u8 = 2;
+// hibits 0 : 0 , 1: byte, 2:word, 4: dword, 6: qword, 8 : double
+const TYPE_NUMBER_ZERO: u8 = 0;
+const TYPE_NUMBER_BYTE: u8 = 1;
+const TYPE_NUMBER_WORD: u8 = 2;
+const TYPE_NUMBER_DWORD: u8 = 4;
+const TYPE_NUMBER_QWORD: u8 = 6;
+const TYPE_NUMBER_REAL: u8 = 8;
+
+const TYPE_USERDATA: u8 = 3;
+const TYPE_SHORT_STRING: u8 = 4;
+// hibits 0~31 : len
+const TYPE_LONG_STRING: u8 = 5;
+const TYPE_TABLE: u8 = 6;
+
+const MAX_COOKIE: usize = 32;
+const MAX_DEPTH: usize = 100;
+
+const INITIAL_BUFFER_SIZE: usize = 128;
+
+fn combine_type(t: u8, v: u8) -> u8 {
+    t | (v << 3)
+}
+
+pub struct Encoder<'a> {
+    buf: Vec<u8>,
+    userdata: Option<v8::Local<'a, v8::Function>>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(userdata: Option<v8::Local<'a, v8::Function>>) -> Self {
+        Encoder {
+            buf: Vec::with_capacity(INITIAL_BUFFER_SIZE),
+            userdata,
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn encode(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        value: v8::Local<'a, v8::Value>,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err("serialize can't pack too depth table".to_string());
+        }
+
+        if value.is_null_or_undefined() {
+            self.encode_nil();
+        } else if value.is_boolean() {
+            self.encode_boolean(value.boolean_value(scope));
+        } else if value.is_string() {
+            let s = value.to_rust_string_lossy(scope);
+            self.encode_string(s.as_bytes());
+        } else if value.is_number() {
+            self.encode_number(value.number_value(scope).unwrap_or(0.0));
+        } else if value.is_big_int() {
+            let v = v8::Local::<v8::BigInt>::try_from(value).map_err(|e| e.to_string())?;
+            self.encode_integer(v.i64_value().0);
+        } else if value.is_object() {
+            let object = value.to_object(scope).unwrap();
+            if let Some(userdata) = self.userdata {
+                if object.instance_of(scope, userdata.into()).unwrap_or(false) {
+                    return self.encode_userdata(scope, object);
+                }
+            }
+            self.encode_table(scope, object, depth + 1)?;
+        } else {
+            let type_name = value.type_of(scope).to_rust_string_lossy(scope);
+            return Err(format!("Unsupport type {} to serialize", type_name));
+        }
+        Ok(())
+    }
+
+    fn encode_nil(&mut self) {
+        self.buf.push(TYPE_NIL);
+    }
+
+    fn encode_boolean(&mut self, v: bool) {
+        self.buf.push(combine_type(TYPE_BOOLEAN, v as u8));
+    }
+
+    // js numbers holding an integer pack as lua integers, all others as lua reals
+    fn encode_number(&mut self, v: f64) {
+        const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+        if v.fract() == 0.0 && v.abs() <= MAX_SAFE_INTEGER {
+            self.encode_integer(v as i64);
+        } else {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_REAL));
+            self.buf.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+
+    fn encode_integer(&mut self, v: i64) {
+        if v == 0 {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_ZERO));
+        } else if v != v as i32 as i64 {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_QWORD));
+            self.buf.extend_from_slice(&v.to_le_bytes());
+        } else if v < 0 {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_DWORD));
+            self.buf.extend_from_slice(&(v as i32).to_le_bytes());
+        } else if v < 0x100 {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_BYTE));
+            self.buf.push(v as u8);
+        } else if v < 0x10000 {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_WORD));
+            self.buf.extend_from_slice(&(v as u16).to_le_bytes());
+        } else {
+            self.buf.push(combine_type(TYPE_NUMBER, TYPE_NUMBER_DWORD));
+            self.buf.extend_from_slice(&(v as u32).to_le_bytes());
+        }
+    }
+
+    fn encode_string(&mut self, s: &[u8]) {
+        let len = s.len();
+        if len < MAX_COOKIE {
+            self.buf.push(combine_type(TYPE_SHORT_STRING, len as u8));
+        } else if len < 0x10000 {
+            self.buf.push(combine_type(TYPE_LONG_STRING, 2));
+            self.buf.extend_from_slice(&(len as u16).to_le_bytes());
+        } else {
+            self.buf.push(combine_type(TYPE_LONG_STRING, 4));
+            self.buf.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        self.buf.extend_from_slice(s);
+    }
+
+    fn encode_userdata(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        object: v8::Local<'a, v8::Object>,
+    ) -> Result<(), String> {
+        let key = v8::String::new(scope, "data").unwrap();
+        let data = object
+            .get(scope, key.into())
+            .and_then(|v| v8::Local::<v8::BigInt>::try_from(v).ok())
+            .ok_or_else(|| "Invalid userdata to serialize".to_string())?;
+        self.buf.push(TYPE_USERDATA);
+        self.buf.extend_from_slice(&data.u64_value().0.to_le_bytes());
+        Ok(())
+    }
+
+    fn encode_table(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        object: v8::Local<'a, v8::Object>,
+        depth: usize,
+    ) -> Result<(), String> {
+        if let Ok(array) = v8::Local::<v8::Array>::try_from(object) {
+            let array_size = array.length() as usize;
+            if array_size >= MAX_COOKIE - 1 {
+                self.buf.push(combine_type(TYPE_TABLE, (MAX_COOKIE - 1) as u8));
+                self.encode_integer(array_size as i64);
+            } else {
+                self.buf.push(combine_type(TYPE_TABLE, array_size as u8));
+            }
+
+            for i in 0..array_size {
+                let v = array
+                    .get_index(scope, i as u32)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                self.encode(scope, v, depth)?;
+            }
+        } else {
+            self.buf.push(combine_type(TYPE_TABLE, 0));
+            let keys = object
+                .get_property_names(scope)
+                .ok_or_else(|| "Invalid object to serialize".to_string())?;
+            for i in 0..keys.length() {
+                let k = keys.get_index(scope, i).unwrap();
+                let v = object
+                    .get(scope, k)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                let k = k.to_string(scope).unwrap().into();
+                self.encode(scope, k, depth)?;
+                self.encode(scope, v, depth)?;
+            }
+        }
+        self.encode_nil();
+        Ok(())
+    }
+}
+
+pub struct Decoder<'a, 'b> {
+    buf: &'b [u8],
+    pos: usize,
+    userdata: Option<v8::Local<'a, v8::Function>>,
+}
+
+impl<'a, 'b> Decoder<'a, 'b> {
+    pub fn new(buf: &'b [u8], userdata: Option<v8::Local<'a, v8::Function>>) -> Self {
+        Decoder {
+            buf,
+            pos: 0,
+            userdata,
+        }
+    }
+
+    pub fn decode(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+    ) -> Result<v8::Local<'a, v8::Array>, String> {
+        let result = v8::Array::new(scope, 0);
+        let mut index = 0;
+        while self.pos < self.buf.len() {
+            let v = self.decode_one(scope, 0)?;
+            result.set_index(scope, index, v);
+            index += 1;
+        }
+        Ok(result)
+    }
+
+    fn invalid_stream(&self, sz: usize) -> String {
+        format!(
+            "Invalid serialize stream {} (pos {}, size {})",
+            sz,
+            self.pos,
+            self.buf.len()
+        )
+    }
+
+    fn read(&mut self, sz: usize) -> Result<&'b [u8], String> {
+        if self.buf.len() - self.pos < sz {
+            return Err(self.invalid_stream(sz));
+        }
+        let buf: &'b [u8] = self.buf;
+        let v = &buf[self.pos..self.pos + sz];
+        self.pos += sz;
+        Ok(v)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn look_u8(&self) -> Result<u8, String> {
+        self.buf
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.invalid_stream(1))
+    }
+
+    fn decode_one(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        depth: usize,
+    ) -> Result<v8::Local<'a, v8::Value>, String> {
+        let t = self.read_u8()?;
+        let cookie = t >> 3;
+        match t & 0x7 {
+            TYPE_NIL => Ok(v8::null(scope).into()),
+            TYPE_BOOLEAN => Ok(v8::Boolean::new(scope, cookie != 0).into()),
+            TYPE_NUMBER => self.decode_number(scope, cookie),
+            TYPE_USERDATA => {
+                let data = LittleEndian::read_u64(self.read(8)?);
+                let data = v8::BigInt::new_from_u64(scope, data).into();
+                match self.userdata {
+                    Some(userdata) => userdata
+                        .new_instance(scope, &[data])
+                        .map(|v| v.into())
+                        .ok_or_else(|| "Create userdata failed".to_string()),
+                    None => Ok(data),
+                }
+            }
+            TYPE_SHORT_STRING => self.decode_string(scope, cookie as usize),
+            TYPE_LONG_STRING => {
+                let len = match cookie {
+                    2 => LittleEndian::read_u16(self.read(2)?) as usize,
+                    4 => LittleEndian::read_u32(self.read(4)?) as usize,
+                    _ => return Err(self.invalid_stream(cookie as usize)),
+                };
+                self.decode_string(scope, len)
+            }
+            TYPE_TABLE => self.decode_table(scope, cookie as usize, depth + 1),
+            _ => Err(self.invalid_stream(t as usize)),
+        }
+    }
+
+    fn decode_number(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        cookie: u8,
+    ) -> Result<v8::Local<'a, v8::Value>, String> {
+        let v: v8::Local<v8::Value> = match cookie {
+            TYPE_NUMBER_ZERO => v8::Integer::new(scope, 0).into(),
+            TYPE_NUMBER_BYTE => v8::Integer::new(scope, self.read_u8()? as i32).into(),
+            TYPE_NUMBER_WORD => {
+                let v = LittleEndian::read_u16(self.read(2)?);
+                v8::Integer::new(scope, v as i32).into()
+            }
+            TYPE_NUMBER_DWORD => {
+                let v = LittleEndian::read_i32(self.read(4)?);
+                v8::Integer::new(scope, v).into()
+            }
+            TYPE_NUMBER_QWORD => {
+                let v = LittleEndian::read_i64(self.read(8)?);
+                v8::BigInt::new_from_i64(scope, v).into()
+            }
+            TYPE_NUMBER_REAL => {
+                let v = LittleEndian::read_f64(self.read(8)?);
+                v8::Number::new(scope, v).into()
+            }
+            _ => return Err(self.invalid_stream(cookie as usize)),
+        };
+        Ok(v)
+    }
+
+    fn decode_string(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        len: usize,
+    ) -> Result<v8::Local<'a, v8::Value>, String> {
+        let bytes = self.read(len)?;
+        v8::String::new_from_utf8(scope, bytes, v8::NewStringType::Normal)
+            .map(|s| s.into())
+            .ok_or_else(|| format!("String too long {}", len))
+    }
+
+    fn decode_table(
+        &mut self,
+        scope: &mut v8::HandleScope<'a>,
+        cookie: usize,
+        depth: usize,
+    ) -> Result<v8::Local<'a, v8::Value>, String> {
+        if depth > MAX_DEPTH {
+            return Err("unserialize can't unpack too depth table".to_string());
+        }
+
+        let mut array_size = cookie;
+        if cookie == MAX_COOKIE - 1 {
+            let t = self.read_u8()?;
+            let cookie = t >> 3;
+            if (t & 0x7) != TYPE_NUMBER || cookie == TYPE_NUMBER_REAL {
+                return Err(self.invalid_stream(t as usize));
+            }
+            let n = self.decode_number(scope, cookie)?;
+            array_size = match v8::Local::<v8::BigInt>::try_from(n) {
+                Ok(n) => n.u64_value().0 as usize,
+                Err(_) => n.uint32_value(scope).unwrap_or(0) as usize,
+            };
+        }
+
+        let table: v8::Local<v8::Object> = if array_size > 0 {
+            let array = v8::Array::new(scope, 0);
+            for i in 0..array_size {
+                let v = self.decode_one(scope, depth)?;
+                array.set_index(scope, i as u32, v);
+            }
+            array.into()
+        } else {
+            v8::Object::new(scope)
+        };
+
+        loop {
+            if (self.look_u8()? & 0x7) == TYPE_NIL {
+                self.pos += 1;
+                break;
+            }
+            let k = self.decode_one(scope, depth)?;
+            let v = self.decode_one(scope, depth)?;
+            table.set(scope, k, v);
+        }
+        Ok(table.into())
+    }
+}
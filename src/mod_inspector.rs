@@ -264,7 +264,14 @@ impl v8::inspector::V8InspectorClientImpl for Inspector {
             return;
         }
 
-        let ws = connect(Url::parse(self.pause_proxy_addr.as_ref().unwrap().as_str()).unwrap());
+        let url = match Url::parse(self.pause_proxy_addr.as_ref().unwrap().as_str()) {
+            Ok(url) => url,
+            Err(err) => {
+                println!("run_message_loop_on_pause invalid addr err:{}", err);
+                return;
+            }
+        };
+        let ws = connect(url);
         if let Err(err) = ws {
             println!(
                 "run_message_loop_on_pause can't connect addr:{} err:{}",
@@ -293,7 +300,10 @@ impl v8::inspector::V8InspectorClientImpl for Inspector {
             let session_index = session_index.unwrap();
 
             let session_id = &msg_str[..session_index];
-            let session_id = session_id.parse::<i32>().unwrap() as i64;
+            let session_id = match session_id.parse::<i32>() {
+                Ok(session_id) => session_id as i64,
+                Err(_) => break,
+            };
 
             if let Some(session) = self.v8_sessions.get_mut(&session_id) {
                 unsafe {
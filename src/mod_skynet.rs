@@ -1,24 +1,33 @@
 use byteorder::{ByteOrder, LittleEndian};
 use libc::{c_int, size_t};
+use std::borrow::Cow;
 use std::cell::Cell;
 use std::cell::RefCell;
 use std::convert::TryFrom;
 use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::string::String;
 
 use deno_core::include_js_files;
+use deno_core::op_async;
 use deno_core::op_sync;
 use deno_core::serde::Deserialize;
+use deno_core::serde::Serialize;
 use deno_core::Extension;
 use deno_core::OpState;
+use deno_core::Resource;
+use deno_core::ResourceId;
 use deno_core::ZeroCopyBuf;
 // use deno_core::serde::Serialize;
+use deno_core::error::bad_resource_id;
+use deno_core::error::custom_error;
 use deno_core::error::AnyError;
 use deno_core::get_args;
 
 use rusty_v8 as v8;
 
 use crate::interface;
+use crate::lua_seri;
 use crate::SkynetContext;
 
 pub fn init() -> Extension {
@@ -32,6 +41,10 @@ pub fn init() -> Extension {
             ("op_skynet_error", op_sync(op_skynet_error)),
             ("op_skynet_send", op_sync(op_skynet_send)),
             ("op_skynet_send_name", op_sync(op_skynet_send_name)),
+            ("op_skynet_call", op_async(op_skynet_call)),
+            ("op_skynet_timeout", op_sync(op_skynet_timeout)),
+            ("op_skynet_sleep", op_async(op_skynet_sleep)),
+            ("op_skynet_wakeup", op_sync(op_skynet_wakeup)),
             ("op_skynet_now", op_sync(op_skynet_now)),
             ("op_skynet_genid", op_sync(op_skynet_genid)),
             (
@@ -39,6 +52,9 @@ pub fn init() -> Extension {
                 op_sync(op_skynet_socket_connect),
             ),
             ("op_skynet_socket_close", op_sync(op_skynet_socket_close)),
+            ("op_skynet_socket_stream", op_sync(op_skynet_socket_stream)),
+            ("op_skynet_socket_read", op_async(op_skynet_socket_read)),
+            ("op_skynet_socket_write", op_async(op_skynet_socket_write)),
             (
                 "op_skynet_socket_shutdown",
                 op_sync(op_skynet_socket_shutdown),
@@ -59,6 +75,11 @@ pub fn init() -> Extension {
                 "op_skynet_set_jslib_paths",
                 op_sync(op_skynet_set_jslib_paths),
             ),
+            ("op_skynet_tokio_stats", op_sync(op_skynet_tokio_stats)),
+            ("op_skynet_wake_stats", op_sync(op_skynet_wake_stats)),
+            ("op_skynet_init_args", op_sync(op_skynet_init_args)),
+            ("op_skynet_loader_path", op_sync(op_skynet_loader_path)),
+            ("op_apply_source_map", op_sync(op_apply_source_map)),
         ])
         .ops_ex(vec![
             ("op_skynet_fetch_message", Box::new(op_skynet_fetch_message)),
@@ -77,6 +98,15 @@ pub fn init() -> Extension {
                 Box::new(op_skynet_socket_send_lowpriority),
             ),
             ("op_skynet_socket_sendto", Box::new(op_skynet_socket_sendto)),
+            ("op_skynet_socket_sendv", Box::new(op_skynet_socket_sendv)),
+            (
+                "op_skynet_socket_sendv_lowpriority",
+                Box::new(op_skynet_socket_sendv_lowpriority),
+            ),
+            ("op_skynet_lua_userdata", Box::new(op_skynet_lua_userdata)),
+            ("op_skynet_lua_pack", Box::new(op_skynet_lua_pack)),
+            ("op_skynet_lua_unpack", Box::new(op_skynet_lua_unpack)),
+            ("op_skynet_send_msg", Box::new(op_skynet_send_msg)),
         ])
         .build()
 }
@@ -95,7 +125,7 @@ pub fn dispatch(
     msg: *const u8,
     sz: size_t,
     remainder_message: i32,
-) {
+) -> bool {
     /*
     let state_rc = deno_core::JsRuntime::state(ctx.runtime.v8_isolate());
     let state = state_rc.borrow_mut();
@@ -109,71 +139,226 @@ pub fn dispatch(
     */
     let skynet = unsafe { &mut *ctx.context };
 
-    let head_sz: usize = 32;
-    let new_bs = get_shared_bs(skynet, ctx, sz + head_sz, skynet.bs_offset);
+    // responses of op_skynet_call and skynet timers are resolved here and never reach the js callback
+    if stype == interface::PTYPE_ERROR && session == 0 {
+        fail_calls_to(skynet, source as u32);
+    } else if stype == interface::PTYPE_RESPONSE || stype == interface::PTYPE_ERROR {
+        let reserve = match skynet.calls.remove(&session) {
+            Some(call) => Some(resolve_call(call, stype, msg, sz)),
+            // the late answer of a timed out call, skynet frees it
+            None => skynet.timed_out.remove(&session).map(|_| false),
+        };
+        if let Some(reserve) = reserve {
+            if remainder_message == 0 && skynet.bs_offset > 0 {
+                flush(ctx, skynet);
+            }
+            return reserve;
+        }
+    }
+
+    let (external, full) = batch_message(skynet, stype, session, source, msg, sz, remainder_message);
+    if full {
+        flush(ctx, skynet);
+    }
+
+    // skynet must not free a message owned by an external ArrayBuffer
+    external
+}
+
+// packs one message into the batch buffer. returns whether the message is owned by an external
+// ArrayBuffer now, so skynet must not free it, and whether the batch has to go to js now
+fn batch_message(
+    skynet: &mut crate::ContextData,
+    stype: c_int,
+    session: c_int,
+    source: c_int,
+    msg: *const u8,
+    sz: size_t,
+    remainder_message: i32,
+) -> (bool, bool) {
+    let external = is_external(skynet.zero_copy_size, stype, sz);
+    let inline_sz = if external { 0 } else { sz };
+    let new_bs = get_shared_bs(skynet, inline_sz + MESSAGE_HEAD_SZ, skynet.bs_offset);
     let buf = unsafe {
         let bs = skynet.bs.as_ref().unwrap();
         get_backing_store_slice_mut(bs, 0, bs.byte_length())
     };
 
-    let mut index = skynet.bs_offset;
-    LittleEndian::write_i32(&mut buf[index..index + 4], stype);
-    index = index + 4;
-    LittleEndian::write_i32(&mut buf[index..index + 4], session);
-    index = index + 4;
-    LittleEndian::write_i32(&mut buf[index..index + 4], source);
-    index = index + 4;
-    LittleEndian::write_u32(&mut buf[index..index + 4], sz as u32);
-    index = index + 4;
-    LittleEndian::write_u64(&mut buf[index..index + 8], msg as u64);
-    if sz > 0 {
-        buf[skynet.bs_offset + head_sz .. skynet.bs_offset + head_sz + sz].copy_from_slice(unsafe { std::slice::from_raw_parts(msg, sz) });
-    }
-
-    skynet.bs_offset = skynet.bs_offset + head_sz + sz;
+    let external_index = if external {
+        let bs = unsafe {
+            v8::ArrayBuffer::new_backing_store_from_ptr(
+                msg as *mut libc::c_void,
+                sz,
+                skynet_free_deleter,
+                std::ptr::null_mut(),
+            )
+        };
+        skynet.bs_external.push(bs.make_shared());
+        // 1-based index into the externals array passed to the callback
+        skynet.bs_external.len() as u32
+    } else {
+        0
+    };
+    let data = if inline_sz > 0 {
+        unsafe { std::slice::from_raw_parts(msg, sz) }
+    } else {
+        &[]
+    };
+    let head = MessageHead {
+        stype,
+        session,
+        source,
+        external: external_index,
+        sz: sz as u64,
+        msg: msg as u64,
+    };
+    skynet.bs_offset = head.pack(buf, skynet.bs_offset, data);
+    skynet.bs_messages.push((stype, session, source));
     if new_bs {
         skynet.bs_flag = skynet.bs_flag | 1;
     }
 
-    if remainder_message == 0 || skynet.bs_offset > 1024 * 1024 {
-        //let scope = &mut ctx.runtime.handle_scope();
-        //let tc_scope = &mut v8::TryCatch::new(scope);
-        let tc_scope = &mut ctx.runtime.handle_scope();
+    (external, should_flush(remainder_message, skynet.bs_offset, skynet.bs_flush_size))
+}
 
-        let mut sz = skynet.bs_offset;
-        if (skynet.bs_flag & 1) == 1 {
-            sz = 1 << 24 | sz;
-        }
+const MESSAGE_HEAD_SZ: usize = 32;
 
-        skynet.bs_flag = 0;
-        skynet.bs_offset = 0;
+// head: type:i32 session:i32 source:i32 external:u32 sz:u64 msg:u64
+struct MessageHead {
+    stype: c_int,
+    session: c_int,
+    source: c_int,
+    external: u32,
+    sz: u64,
+    msg: u64,
+}
 
-        let v8_sz = v8::Integer::new(tc_scope, sz as i32).into();
-        if let Some(js_recv_cb_handle) = skynet.cb.clone() {
-            let this = v8::undefined(tc_scope).into();
-            let js_recv_cb = js_recv_cb_handle.get(tc_scope);
-            js_recv_cb.call(tc_scope, this, &[v8_sz]);
-        }
+impl MessageHead {
+    // writes the head and the inline data at offset, returns the offset of the next message
+    fn pack(&self, buf: &mut [u8], offset: usize, data: &[u8]) -> usize {
+        LittleEndian::write_i32(&mut buf[offset..offset + 4], self.stype);
+        LittleEndian::write_i32(&mut buf[offset + 4..offset + 8], self.session);
+        LittleEndian::write_i32(&mut buf[offset + 8..offset + 12], self.source);
+        LittleEndian::write_u32(&mut buf[offset + 12..offset + 16], self.external);
+        LittleEndian::write_u64(&mut buf[offset + 16..offset + 24], self.sz);
+        LittleEndian::write_u64(&mut buf[offset + 24..offset + 32], self.msg);
+        let offset = offset + MESSAGE_HEAD_SZ;
+        buf[offset..offset + data.len()].copy_from_slice(data);
+        offset + data.len()
     }
+}
 
+// large messages are handed to js as an external ArrayBuffer that owns the skynet pointer
+fn is_external(zero_copy_size: usize, stype: c_int, sz: usize) -> bool {
+    zero_copy_size > 0 && sz >= zero_copy_size && stype != interface::PTYPE_SOCKET
+}
 
-    /*
-    let global = context.global(tc_scope).into();
-    let js_recv_cb = skynet
-        .cb
-        .as_ref()
-        .map(|cb| v8::Local::new(tc_scope, cb))
-        .unwrap();
-    drop(state);
-    js_recv_cb.call(tc_scope, global, &[v8_new_bs]);
-    */
+fn should_flush(remainder_message: i32, offset: usize, flush_size: usize) -> bool {
+    remainder_message == 0 || offset >= flush_size
+}
 
-    /*
+// what one call of the js callback gets
+struct Batch {
+    sz: usize,
+    new_bs: bool,
+    messages: Vec<(c_int, c_int, c_int)>,
+    externals: Vec<v8::SharedRef<v8::BackingStore>>,
+}
+
+// the batch starts over afterwards, in the same buffer unless a message doesn't fit
+fn take_batch(skynet: &mut crate::ContextData) -> Batch {
+    let batch = Batch {
+        sz: skynet.bs_offset,
+        new_bs: (skynet.bs_flag & 1) == 1,
+        messages: std::mem::take(&mut skynet.bs_messages),
+        externals: std::mem::take(&mut skynet.bs_external),
+    };
+    skynet.bs_flag = 0;
+    skynet.bs_offset = 0;
+    batch
+}
+
+fn flush(ctx: &mut crate::snjs, skynet: &mut crate::ContextData) {
+    let scope = &mut ctx.runtime().handle_scope();
+    let tc_scope = &mut v8::TryCatch::new(scope);
+
+    let Batch {
+        sz,
+        new_bs,
+        messages,
+        externals: bs_external,
+    } = take_batch(skynet);
+
+    // a js number holds sizes up to 2^53, the new backing store flag goes separately
+    let v8_sz = v8::Number::new(tc_scope, sz as f64).into();
+    let v8_new_bs = v8::Boolean::new(tc_scope, new_bs).into();
+    let v8_externals = if bs_external.is_empty() {
+        v8::undefined(tc_scope).into()
+    } else {
+        let externals = v8::Array::new(tc_scope, bs_external.len() as i32);
+        for (i, bs) in bs_external.iter().enumerate() {
+            let ab = v8::ArrayBuffer::with_backing_store(tc_scope, bs);
+            externals.set_index(tc_scope, i as u32, ab.into());
+        }
+        externals.into()
+    };
+    // js returns how many messages it dispatched, a failing one replies PTYPE_ERROR itself
+    let mut dispatched = 0;
+    if let Some(js_recv_cb_handle) = skynet.cb.clone() {
+        let this = v8::undefined(tc_scope).into();
+        let js_recv_cb = js_recv_cb_handle.get(tc_scope);
+        if let Some(count) = js_recv_cb.call(tc_scope, this, &[v8_sz, v8_new_bs, v8_externals]) {
+            dispatched = count.uint32_value(tc_scope).unwrap_or(0) as usize;
+        }
+    }
+
+    // terminated by snjs_signal or the heap limit, dispatch_impl reports it
+    if tc_scope.has_terminated() {
+        return;
+    }
+    // the callers of the messages js never reached would wait forever
+    let missed = &messages[std::cmp::min(dispatched, messages.len())..];
     if tc_scope.has_caught() {
         let exception = tc_scope.exception().unwrap();
-        let err1: Result<(), deno_core::error::AnyError> = deno_core::exception_to_err_result(tc_scope, exception, false);
+        let err = deno_core::error::JsError::from_v8_exception(tc_scope, exception);
+        dispatch_error(skynet, missed, &err.to_string());
+    }
+    reply_error(skynet, missed);
+}
+
+extern "C" fn skynet_free_deleter(
+    data: *mut libc::c_void,
+    _byte_length: usize,
+    _deleter_data: *mut libc::c_void,
+) {
+    unsafe { interface::skynet_free(data) };
+}
+
+fn dispatch_error(skynet: &crate::ContextData, messages: &[(c_int, c_int, c_int)], err: &str) {
+    let mut err_msg = format!("SkynetTs dispatch callback error: {}", err);
+    for (stype, session, source) in messages {
+        err_msg += &format!("\n\tmessage type:{} session:{} source:{:08x}", stype, session, source);
+    }
+    crate::report_error(skynet.skynet, &err_msg);
+}
+
+fn reply_error(skynet: &crate::ContextData, messages: &[(c_int, c_int, c_int)]) {
+    for &(stype, session, source) in messages {
+        if session == 0 || stype == interface::PTYPE_RESPONSE || stype == interface::PTYPE_ERROR {
+            continue;
+        }
+        unsafe {
+            interface::skynet_send(
+                skynet.skynet,
+                0,
+                source as u32,
+                interface::PTYPE_ERROR,
+                session,
+                std::ptr::null(),
+                0,
+            )
+        };
     }
-    */
 }
 
 pub fn op_skynet_callback(
@@ -206,9 +391,9 @@ pub fn op_skynet_shared_bs(
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
-    let shared_ab = {
-        let ab = v8::SharedArrayBuffer::with_backing_store(scope, skynet.bs.as_mut().unwrap());
-        ab
+    let shared_ab = match skynet.bs.as_mut() {
+        Some(bs) => v8::SharedArrayBuffer::with_backing_store(scope, bs),
+        None => return throw_type_error(scope, "shared backing store not created"),
     };
     rv.set(shared_ab.into())
 }
@@ -224,9 +409,9 @@ pub fn op_skynet_shared_bs_temp(
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
-    let shared_ab = {
-        let ab = v8::SharedArrayBuffer::with_backing_store(scope, skynet.bs_temp.as_mut().unwrap());
-        ab
+    let shared_ab = match skynet.bs_temp.as_mut() {
+        Some(bs) => v8::SharedArrayBuffer::with_backing_store(scope, bs),
+        None => return throw_type_error(scope, "temp backing store not created"),
     };
     rv.set(shared_ab.into())
 }
@@ -240,9 +425,54 @@ pub fn op_skynet_set_jslib_paths(
     let skynet = unsafe { &mut **skynet };
 
     skynet.module_search_paths = paths.split(";").map(|p| p.to_owned()).collect();
+    skynet.resolve_cache.clear();
     Ok(())
 }
 
+pub fn op_skynet_init_args(state: &mut OpState, _args: (), _: ()) -> Result<Vec<String>, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    Ok(skynet.init_args.clone())
+}
+
+pub fn op_skynet_loader_path(state: &mut OpState, _args: (), _: ()) -> Result<String, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    Ok(skynet.loader_path.clone())
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplySourceMap {
+    file_name: String,
+    line_number: i64,
+    column_number: i64,
+}
+
+// called by Error.prepareStackTrace for every frame, see applySourceMaps in init_cb
+pub fn op_apply_source_map(
+    state: &mut OpState,
+    args: ApplySourceMap,
+    _: (),
+) -> Result<ApplySourceMap, AnyError> {
+    let source_maps = state.borrow::<crate::loader::SharedSourceMaps>();
+    let position = source_maps.borrow_mut().original_position(
+        &args.file_name,
+        args.line_number,
+        args.column_number,
+    );
+    Ok(match position {
+        Some((file_name, line_number, column_number, _)) => ApplySourceMap {
+            file_name,
+            line_number,
+            column_number,
+        },
+        None => args,
+    })
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CommandArgs {
@@ -257,16 +487,15 @@ pub fn op_skynet_command(
     let skynet = state.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
-    let cmd = std::ffi::CString::new(args.cmd).unwrap();
-    let param = std::ffi::CString::new(args.param).unwrap();
+    let cmd = std::ffi::CString::new(args.cmd)?;
+    let param = std::ffi::CString::new(args.param)?;
 
     let result = unsafe { interface::skynet_command(skynet.skynet, cmd.as_ptr(), param.as_ptr()) };
     if result != std::ptr::null() {
         Ok(Some(unsafe {
             std::ffi::CStr::from_ptr(result)
-                .to_str()
-                .unwrap()
-                .to_string()
+                .to_string_lossy()
+                .into_owned()
         }))
     } else {
         Ok(None)
@@ -277,10 +506,7 @@ pub fn op_skynet_error(state: &mut OpState, err: String, _: ()) -> Result<u32, A
     let skynet = state.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
-    let err = std::ffi::CString::new(err).unwrap();
-
-    let error = std::ffi::CString::new(err).unwrap();
-    unsafe { interface::skynet_error(skynet.skynet, error.as_ptr()) };
+    crate::report_error(skynet.skynet, &err);
 
     Ok(1)
 }
@@ -293,7 +519,7 @@ fn merge_bufs(zero_copy: &[ZeroCopyBuf]) -> (*const libc::c_void, libc::size_t)
 
     let dest = unsafe {
         // libc::malloc(sz)
-        interface::skynet_malloc(sz as u32) as *mut libc::c_void
+        interface::skynet_malloc(sz) as *mut libc::c_void
     };
     sz = 0;
     for buf in zero_copy {
@@ -352,7 +578,7 @@ pub fn op_skynet_send_name(
     let skynet = state.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
-    let name = std::ffi::CString::new(args.name).unwrap();
+    let name = std::ffi::CString::new(args.name)?;
     let (msg_buf, msg_sz) = merge_bufs(&bufs);
 
     let session = unsafe {
@@ -369,11 +595,351 @@ pub fn op_skynet_send_name(
     Ok(session)
 }
 
+#[derive(Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum CallDest {
+    Addr(u32),
+    Name(String),
+}
+
+pub enum CallResult {
+    Response(*const u8, size_t),
+    Error,
+}
+
+impl CallResult {
+    // hands the message over, it is not freed on drop anymore
+    fn take_response(&mut self) -> Option<(*const u8, size_t)> {
+        match self {
+            CallResult::Response(msg, sz) => {
+                let response = (*msg, *sz);
+                *msg = std::ptr::null();
+                Some(response)
+            }
+            CallResult::Error => None,
+        }
+    }
+}
+
+// a response nobody took, e.g. the op future was dropped by the shutdown timeout
+impl Drop for CallResult {
+    fn drop(&mut self) {
+        if let CallResult::Response(msg, _) = *self {
+            if !msg.is_null() {
+                unsafe { interface::skynet_free(msg as *const libc::c_void) };
+            }
+        }
+    }
+}
+
+pub type CallReceiver = tokio::sync::oneshot::Receiver<CallResult>;
+
+pub struct PendingCall {
+    // None for skynet timers
+    dest: Option<CallDest>,
+    // None once op_skynet_wakeup finished the sleep early
+    tx: Option<tokio::sync::oneshot::Sender<CallResult>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallArgs {
+    dest: CallDest,
+    ptype: i32,
+    // ms, 0 waits forever
+    timeout: u64,
+    // a message resource from pack_msg, bufs are ignored when it is set
+    msg: Option<ResourceId>,
+}
+
+#[derive(Serialize)]
+pub struct CallResponse {
+    msg: ResourceId,
+    sz: usize,
+}
+
+// send a request and wait for the response, js owns the returned message and frees it
+pub async fn op_skynet_call(
+    state: Rc<RefCell<OpState>>,
+    args: CallArgs,
+    bufs: Vec<ZeroCopyBuf>,
+) -> Result<CallResponse, AnyError> {
+    let (session, rx) = {
+        let mut state = state.borrow_mut();
+        let (msg_buf, msg_sz) = match args.msg {
+            Some(rid) => {
+                let (msg, sz) = take_message(&mut state, rid)?;
+                (msg as *const libc::c_void, sz)
+            }
+            None => merge_bufs(&bufs),
+        };
+        let skynet = state.borrow_mut::<SkynetContext>();
+        let skynet = unsafe { &mut **skynet };
+
+        // local names are resolved now, so the call fails when that service exits
+        let dest = match args.dest {
+            CallDest::Name(name) if name.starts_with('.') || name.starts_with(':') => {
+                let handle = match std::ffi::CString::new(name.as_str()) {
+                    Ok(cname) => unsafe { interface::skynet_queryname(skynet.skynet, cname.as_ptr()) },
+                    Err(_) => 0,
+                };
+                if handle == 0 {
+                    unsafe { interface::skynet_free(msg_buf) };
+                    return Err(custom_error("CallFailed", format!("call unknown service {}", name)));
+                }
+                CallDest::Addr(handle)
+            }
+            dest => dest,
+        };
+
+        let ptype = args.ptype | interface::PTYPE_TAG_DONTCOPY | interface::PTYPE_TAG_ALLOCSESSION;
+        let session = match &dest {
+            CallDest::Addr(dest) => unsafe {
+                interface::skynet_send(skynet.skynet, 0, *dest, ptype, 0, msg_buf, msg_sz)
+            },
+            CallDest::Name(name) => {
+                let name = match std::ffi::CString::new(name.as_str()) {
+                    Ok(name) => name,
+                    Err(err) => {
+                        unsafe { interface::skynet_free(msg_buf) };
+                        return Err(err.into());
+                    }
+                };
+                unsafe {
+                    interface::skynet_sendname(skynet.skynet, 0, name.as_ptr(), ptype, 0, msg_buf, msg_sz)
+                }
+            }
+        };
+        if session < 0 {
+            return Err(custom_error("CallFailed", format!("call send failed {}", session)));
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        skynet.calls.insert(
+            session,
+            PendingCall {
+                dest: Some(dest),
+                tx: Some(tx),
+            },
+        );
+        (session, rx)
+    };
+
+    let mut result = if args.timeout > 0 {
+        match tokio::time::timeout(std::time::Duration::from_millis(args.timeout), rx).await {
+            Ok(result) => result,
+            Err(_) => {
+                let mut state = state.borrow_mut();
+                let skynet = state.borrow_mut::<SkynetContext>();
+                let skynet = unsafe { &mut **skynet };
+                // its late response is dropped in dispatch, fail_calls_to forgets it if the callee exits
+                if let Some(PendingCall { dest: Some(dest), .. }) = skynet.calls.remove(&session) {
+                    skynet.timed_out.insert(session, dest);
+                }
+                return Err(custom_error(
+                    "TimedOut",
+                    format!("call session {} timeout after {}ms", session, args.timeout),
+                ));
+            }
+        }
+    } else {
+        rx.await
+    };
+
+    match result.as_mut().map(CallResult::take_response) {
+        Ok(Some((msg, sz))) => Ok(CallResponse {
+            msg: add_message(&mut state.borrow_mut(), msg as *mut libc::c_void, sz),
+            sz,
+        }),
+        Ok(None) => Err(custom_error("CallFailed", format!("call session {} failed", session))),
+        Err(_) => Err(custom_error("CallFailed", format!("call session {} canceled", session))),
+    }
+}
+
+fn resolve_call(call: PendingCall, stype: c_int, msg: *const u8, sz: size_t) -> bool {
+    let tx = match call.tx {
+        Some(tx) => tx,
+        None => return false,
+    };
+    if stype == interface::PTYPE_ERROR {
+        let _ = tx.send(CallResult::Error);
+        return false;
+    }
+    if call.dest.is_none() {
+        let _ = tx.send(CallResult::Response(std::ptr::null(), 0));
+        return false;
+    }
+    // keep the message for js, it is freed by skynet if the caller is already gone
+    match tx.send(CallResult::Response(msg, sz)) {
+        Ok(()) => true,
+        Err(mut result) => {
+            result.take_response();
+            false
+        }
+    }
+}
+
+// source exited, the calls waiting on it never get a response
+// local names are stored resolved, only calls to global harbor names are not matched
+fn fail_calls_to(skynet: &mut crate::ContextData, source: u32) {
+    let sessions: Vec<c_int> = skynet
+        .calls
+        .iter()
+        .filter(|(_, call)| matches!(call.dest, Some(CallDest::Addr(dest)) if dest == source))
+        .map(|(session, _)| *session)
+        .collect();
+    for session in sessions {
+        if let Some(call) = skynet.calls.remove(&session) {
+            resolve_call(call, interface::PTYPE_ERROR, std::ptr::null(), 0);
+        }
+    }
+    skynet
+        .timed_out
+        .retain(|_, dest| !matches!(dest, CallDest::Addr(dest) if *dest == source));
+}
+
+// reject the pending calls and tell their services we are gone, as skynet.exit does
+pub fn cancel_calls(skynet: &mut crate::ContextData) {
+    let calls = std::mem::take(&mut skynet.calls);
+    let timed_out = std::mem::take(&mut skynet.timed_out);
+    let mut dests: Vec<CallDest> = Vec::new();
+    let pending = calls.into_iter().filter_map(|(_, call)| call.dest);
+    for dest in pending.chain(timed_out.into_iter().map(|(_, dest)| dest)) {
+        if !dests.contains(&dest) {
+            dests.push(dest);
+        }
+    }
+    skynet.timers.clear();
+
+    for dest in dests {
+        match dest {
+            CallDest::Addr(dest) => unsafe {
+                interface::skynet_send(skynet.skynet, 0, dest, interface::PTYPE_ERROR, 0, std::ptr::null(), 0)
+            },
+            CallDest::Name(name) => match std::ffi::CString::new(name) {
+                Ok(name) => unsafe {
+                    interface::skynet_sendname(
+                        skynet.skynet,
+                        0,
+                        name.as_ptr(),
+                        interface::PTYPE_ERROR,
+                        0,
+                        std::ptr::null(),
+                        0,
+                    )
+                },
+                Err(_) => 0,
+            },
+        };
+    }
+}
+
+// start a skynet timer, its response is awaited with op_skynet_sleep
+pub fn op_skynet_timeout(state: &mut OpState, ti: i32, _: ()) -> Result<i32, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    let session = unsafe {
+        interface::skynet_send(
+            skynet.skynet,
+            0,
+            0,
+            interface::PTYPE_TAG_ALLOCSESSION,
+            0,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if unsafe { interface::skynet_timeout(skynet.handle, ti, session) } < 0 {
+        return Err(custom_error("TimerFailed", format!("skynet timeout {} failed", ti)));
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    skynet.calls.insert(
+        session,
+        PendingCall {
+            dest: None,
+            tx: Some(tx),
+        },
+    );
+    skynet.timers.insert(session, rx);
+    Ok(session)
+}
+
+pub async fn op_skynet_sleep(
+    state: Rc<RefCell<OpState>>,
+    session: i32,
+    _: (),
+) -> Result<(), AnyError> {
+    let rx = {
+        let mut state = state.borrow_mut();
+        let skynet = state.borrow_mut::<SkynetContext>();
+        let skynet = unsafe { &mut **skynet };
+        skynet.timers.remove(&session)
+    };
+    let rx = rx.ok_or_else(|| custom_error("TimerFailed", format!("unknown timer session {}", session)))?;
+
+    match rx.await {
+        Ok(_) => Ok(()),
+        Err(_) => Err(custom_error("TimerFailed", format!("timer session {} canceled", session))),
+    }
+}
+
+// finish a sleep before its timer fires, returns false if it's not a pending skynet timer
+pub fn op_skynet_wakeup(state: &mut OpState, session: i32, _: ()) -> Result<bool, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    let tx = match skynet.calls.get_mut(&session) {
+        Some(call) if call.dest.is_none() => call.tx.take(),
+        _ => None,
+    };
+    match tx {
+        Some(tx) => {
+            let _ = tx.send(CallResult::Response(std::ptr::null(), 0));
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
 pub fn op_skynet_now(_state: &mut OpState, _args: (), _: ()) -> Result<u64, AnyError> {
     let now = unsafe { interface::skynet_now() };
     Ok(now)
 }
 
+pub fn op_skynet_tokio_stats(
+    state: &mut OpState,
+    _args: (),
+    _: (),
+) -> Result<crate::tokio_pool::TokioStats, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+    let ctx = unsafe { &*(skynet.ctx as *const crate::snjs) };
+
+    let pool = unsafe { &*ctx.tokio_rt };
+    Ok(pool.stats())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WakeStats {
+    sent: u64,
+    suppressed: u64,
+}
+
+// async wakeup messages queued by the waker, and the wakes coalesced into them
+pub fn op_skynet_wake_stats(state: &mut OpState, _args: (), _: ()) -> Result<WakeStats, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+    let ctx = unsafe { &*(skynet.ctx as *const crate::snjs) };
+
+    Ok(WakeStats {
+        sent: ctx.wake_stats.sent.load(Ordering::Relaxed),
+        suppressed: ctx.wake_stats.suppressed.load(Ordering::Relaxed),
+    })
+}
+
 pub fn op_skynet_genid(state: &mut OpState, _args: (), _: ()) -> Result<i32, AnyError> {
     let skynet = state.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
@@ -393,18 +959,79 @@ pub fn op_skynet_genid(state: &mut OpState, _args: (), _: ()) -> Result<i32, Any
     Ok(session)
 }
 
+// a skynet_malloc message owned by js, freed on close unless it was handed to skynet
+pub struct MessageResource {
+    msg: Cell<*mut libc::c_void>,
+    sz: usize,
+}
+
+impl MessageResource {
+    fn new(msg: *mut libc::c_void, sz: usize) -> Self {
+        MessageResource {
+            msg: Cell::new(msg),
+            sz,
+        }
+    }
+
+    fn into_raw(&self) -> (*mut libc::c_void, usize) {
+        (self.msg.replace(std::ptr::null_mut()), self.sz)
+    }
+}
+
+impl Resource for MessageResource {
+    fn name(&self) -> Cow<str> {
+        "skynetMessage".into()
+    }
+}
+
+impl Drop for MessageResource {
+    fn drop(&mut self) {
+        let msg = self.msg.get();
+        if !msg.is_null() {
+            unsafe { interface::skynet_free(msg) };
+        }
+    }
+}
+
+pub fn add_message(op_state: &mut OpState, msg: *mut libc::c_void, sz: usize) -> ResourceId {
+    op_state.resource_table.add(MessageResource::new(msg, sz))
+}
+
+// the message leaves the resource table, the caller owns the pointer
+pub fn take_message(op_state: &mut OpState, rid: ResourceId) -> Result<(*mut libc::c_void, usize), AnyError> {
+    let message = op_state
+        .resource_table
+        .take::<MessageResource>(rid)
+        .ok_or_else(bad_resource_id)?;
+    Ok(message.into_raw())
+}
+
+fn message_rid(value: v8::Local<v8::Value>) -> Option<ResourceId> {
+    v8::Local::<v8::Integer>::try_from(value)
+        .ok()
+        .and_then(|rid| ResourceId::try_from(rid.value()).ok())
+}
+
+fn throw_bad_message<'s>(scope: &mut v8::HandleScope<'s>, rid: Option<ResourceId>) {
+    match rid {
+        Some(rid) => throw_type_error(scope, format!("Bad message resource ID {}", rid)),
+        None => throw_type_error(scope, "Expect a message resource ID"),
+    }
+}
+
 pub fn op_skynet_free(
     _state: std::cell::RefMut<deno_core::JsRuntimeState>,
-    _op_state: Rc<RefCell<OpState>>,
+    op_state: Rc<RefCell<OpState>>,
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     _rv: &mut v8::ReturnValue,
 ) {
-    let msg = get_args!(scope, v8::BigInt, args, 1).u64_value().0;
-    unsafe {
-        //libc::free(msg as *mut libc::c_void)
-        interface::skynet_free(msg as *mut libc::c_void)
-    };
+    let rid = message_rid(args.get(1));
+    let freed = rid.and_then(|rid| take_message(&mut op_state.borrow_mut(), rid).ok());
+    match freed {
+        Some((msg, _)) => unsafe { interface::skynet_free(msg) },
+        None => throw_bad_message(scope, rid),
+    }
 }
 
 #[allow(clippy::mut_from_ref)]
@@ -420,23 +1047,46 @@ pub unsafe fn get_backing_store_slice_mut(
 
 pub fn op_skynet_fetch_message(
     _state: std::cell::RefMut<deno_core::JsRuntimeState>,
-    _op_state: Rc<RefCell<OpState>>,
+    op_state: Rc<RefCell<OpState>>,
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     rv: &mut v8::ReturnValue,
 ) {
-    let msg = get_args!(scope, v8::BigInt, args, 1).u64_value().0;
+    let rid = message_rid(args.get(1));
     let sz = get_args!(scope, v8::Integer, args, 2).value() as libc::size_t;
     let buffer = get_args!(scope, v8::ArrayBuffer, args, 3);
     let buffer = v8::ArrayBuffer::get_backing_store(&buffer);
     let offset = get_args!(scope, v8::Integer, args, 4).value() as libc::size_t;
 
+    let message = rid.and_then(|rid| {
+        op_state
+            .borrow()
+            .resource_table
+            .get::<MessageResource>(rid)
+    });
+    let message = match message {
+        Some(message) => message,
+        None => return throw_bad_message(scope, rid),
+    };
+    let msg = message.msg.get();
+    if sz > message.sz {
+        return throw_type_error(
+            scope,
+            format!("fetch message out of range sz:{} message:{}", sz, message.sz),
+        );
+    }
+    if offset + sz > buffer.byte_length() {
+        return throw_type_error(
+            scope,
+            format!("fetch message out of range offset:{} sz:{} buffer:{}", offset, sz, buffer.byte_length()),
+        );
+    }
     if sz > 0 {
-        let buf = unsafe { get_backing_store_slice_mut(&buffer, offset, sz + offset) };
+        let buf = unsafe { get_backing_store_slice_mut(&buffer, offset, sz) };
         buf.copy_from_slice(unsafe { std::slice::from_raw_parts(msg as *const u8, sz) });
     }
 
-    let v8_sz = v8::Integer::new(scope, sz as i32).into();
+    let v8_sz = v8::Number::new(scope, sz as f64).into();
     rv.set(v8_sz);
 }
 
@@ -487,21 +1137,18 @@ pub fn op_skynet_socket_shutdown(
 
 pub fn get_shared_bs(
     skynet: &mut crate::ContextData,
-    ctx: &mut crate::snjs,
     sz: usize,
     offset: usize,
 ) -> bool {
     let shared_bs = &skynet.bs;
     let mut new_bs = false;
-    let alloc_sz = sz + offset;
-    let _bs = match shared_bs {
-        Some(bs) if bs.byte_length() >= alloc_sz => bs,
-        _ => {
-            //let mut buf = Vec::new();
-            //buf.resize(alloc_sz, 0);
-            //let bs = v8::SharedArrayBuffer::new_backing_store_from_boxed_slice(buf.into_boxed_slice(),);
-            let scope = &mut ctx.runtime.handle_scope();
-            let bs = v8::SharedArrayBuffer::new_backing_store(scope, alloc_sz*2);
+    let capacity = shared_bs.as_ref().map_or(0, |bs| bs.byte_length());
+    let _bs = match shared_bs_grow_size(capacity, sz, offset) {
+        None => shared_bs.as_ref().unwrap(),
+        Some(alloc_sz) => {
+            // zeroed like v8 does, but without an isolate so the batching runs in tests
+            let buf = vec![0u8; alloc_sz];
+            let bs = v8::SharedArrayBuffer::new_backing_store_from_boxed_slice(buf.into_boxed_slice());
             let bs = Some(bs.make_shared());
             new_bs = true;
 
@@ -526,6 +1173,16 @@ pub fn get_shared_bs(
     new_bs
 }
 
+// the new size of the batch buffer when sz more bytes don't fit after offset
+fn shared_bs_grow_size(capacity: usize, sz: usize, offset: usize) -> Option<usize> {
+    let alloc_sz = sz + offset;
+    if capacity >= alloc_sz {
+        None
+    } else {
+        Some(alloc_sz * 2)
+    }
+}
+
 pub fn get_shared_bs_temp(
     skynet: &mut crate::ContextData,
     scope: &mut v8::HandleScope,
@@ -595,6 +1252,22 @@ pub fn op_skynet_socket_unpack(
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
+    let errmsg: &[u8] = if errmsg_len > 0 {
+        unsafe {
+            std::slice::from_raw_parts(
+                (msg + interface::SKYNET_SOCKET_MESSAGE_SIZE as u64) as *const u8,
+                errmsg_len,
+            )
+        }
+    } else {
+        &[]
+    };
+    if feed_stream(skynet, &op_state_rc, socket_message, errmsg) {
+        // null tells the socket library the message was consumed
+        rv.set(v8::null(scope).into());
+        return;
+    }
+
     let new_bs = get_shared_bs_temp(skynet, scope, buffer_len);
     let buf = unsafe {
         let bs = skynet.bs_temp.as_ref().unwrap();
@@ -744,7 +1417,7 @@ pub fn op_skynet_socket_udp_connect(
 pub type BufVec = smallvec::SmallVec<[ZeroCopyBuf; 2]>;
 pub fn op_skynet_alloc_msg(
     _state: std::cell::RefMut<deno_core::JsRuntimeState>,
-    _op_state: Rc<RefCell<OpState>>,
+    op_state: Rc<RefCell<OpState>>,
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
     rv: &mut v8::ReturnValue,
@@ -773,7 +1446,7 @@ pub fn op_skynet_alloc_msg(
 
     let dest = unsafe {
         //libc::malloc(sz)
-        interface::skynet_malloc(sz as u32) as *mut libc::c_void
+        interface::skynet_malloc(sz) as *mut libc::c_void
     };
     sz = 0;
     for buf in &mut bufs {
@@ -784,14 +1457,191 @@ pub fn op_skynet_alloc_msg(
         sz += buf_sz;
     }
 
-    let v8_dest = v8::BigInt::new_from_u64(scope, dest as u64).into();
-    let v8_sz = v8::Integer::new(scope, sz as i32).into();
+    let rid = add_message(&mut op_state.borrow_mut(), dest, sz);
+    let v8_rid = v8::Integer::new_from_unsigned(scope, rid).into();
+    let v8_sz = v8::Number::new(scope, sz as f64).into();
+    let v8_ret = v8::Array::new(scope, 2);
+    v8_ret.set_index(scope, 0, v8_rid);
+    v8_ret.set_index(scope, 1, v8_sz);
+    rv.set(v8_ret.into());
+}
+
+pub struct LuaUserData(v8::Global<v8::Function>);
+
+fn lua_userdata<'s>(
+    op_state: &Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope<'s>,
+) -> Option<v8::Local<'s, v8::Function>> {
+    let op_state = op_state.borrow();
+    op_state
+        .try_borrow::<LuaUserData>()
+        .map(|userdata| v8::Local::new(scope, &userdata.0))
+}
+
+pub fn op_skynet_lua_userdata(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: &mut v8::ReturnValue,
+) {
+    let ctor = match v8::Local::<v8::Function>::try_from(args.get(1)) {
+        Ok(ctor) => ctor,
+        Err(err) => return throw_type_error(scope, err.to_string()),
+    };
+
+    op_state
+        .borrow_mut()
+        .put(LuaUserData(v8::Global::new(scope, ctor)));
+}
+
+pub fn op_skynet_lua_pack(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+) {
+    let userdata = lua_userdata(&op_state, scope);
+    let mut encoder = lua_seri::Encoder::new(userdata);
+    for idx in 1..args.length() {
+        if let Err(err) = encoder.encode(scope, args.get(idx), 0) {
+            return throw_type_error(scope, err);
+        }
+    }
+    let buf = encoder.into_inner();
+
+    let sz = buf.len();
+    let dest = unsafe {
+        let dest = interface::skynet_malloc(sz) as *mut libc::c_void;
+        libc::memcpy(dest, buf.as_ptr() as *const libc::c_void, sz);
+        dest
+    };
+
+    let rid = add_message(&mut op_state.borrow_mut(), dest, sz);
+    let v8_dest = v8::Integer::new_from_unsigned(scope, rid).into();
+    let v8_sz = v8::Number::new(scope, sz as f64).into();
     let v8_ret = v8::Array::new(scope, 2);
     v8_ret.set_index(scope, 0, v8_dest);
     v8_ret.set_index(scope, 1, v8_sz);
     rv.set(v8_ret.into());
 }
 
+pub fn op_skynet_lua_unpack(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+) {
+    // (msg: rid) for a skynet message, (buf: ArrayBufferView, offset, sz) otherwise
+    let msg = args.get(1);
+    let backing_store;
+    let message;
+    let buf = if msg.is_number() {
+        let rid = message_rid(msg);
+        message = match rid.and_then(|rid| op_state.borrow().resource_table.get::<MessageResource>(rid)) {
+            Some(message) => message,
+            None => return throw_bad_message(scope, rid),
+        };
+        let ptr = message.msg.get() as *const u8;
+        if ptr.is_null() || message.sz == 0 {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(ptr, message.sz) }
+        }
+    } else if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(msg) {
+        let offset = get_args!(scope, v8::Integer, args, 2).value() as usize;
+        let sz = get_args!(scope, v8::Integer, args, 3).value() as usize;
+        backing_store = match view.buffer(scope) {
+            Some(buffer) => buffer.get_backing_store(),
+            None => return throw_type_error(scope, "Invalid buffer to unpack"),
+        };
+        let view_offset = view.byte_offset();
+        if offset + sz > view.byte_length() {
+            return throw_type_error(
+                scope,
+                format!("unpack out of range offset:{} sz:{} buffer:{}", offset, sz, view.byte_length()),
+            );
+        }
+        if sz == 0 {
+            &[]
+        } else {
+            unsafe { get_backing_store_slice_mut(&backing_store, view_offset + offset, sz) }
+        }
+    } else {
+        return throw_type_error(scope, "Expect message resource ID or ArrayBufferView to unpack");
+    };
+
+    let userdata = lua_userdata(&op_state, scope);
+    match lua_seri::Decoder::new(buf, userdata).decode(scope) {
+        Ok(values) => rv.set(values.into()),
+        Err(err) => throw_type_error(scope, err),
+    }
+}
+
+pub fn op_skynet_send_msg(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+) {
+    let addr = args.get(1);
+    let ptype = get_args!(scope, v8::Integer, args, 2).value() as c_int;
+    let session = get_args!(scope, v8::Integer, args, 3).value() as c_int;
+    let rid = message_rid(args.get(4));
+
+    let mut op_state_rc = op_state.borrow_mut();
+    let (msg, sz) = match rid.map(|rid| take_message(&mut op_state_rc, rid)) {
+        Some(Ok(message)) => message,
+        _ => {
+            drop(op_state_rc);
+            return throw_bad_message(scope, rid);
+        }
+    };
+    let skynet = op_state_rc.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    // the message is owned by skynet from here on, it is freed there if the send fails
+    let session = if addr.is_string() {
+        let name = addr.to_rust_string_lossy(scope);
+        let name = match std::ffi::CString::new(name) {
+            Ok(name) => name,
+            Err(err) => {
+                unsafe { interface::skynet_free(msg as *const libc::c_void) };
+                return throw_type_error(scope, err.to_string());
+            }
+        };
+        unsafe {
+            interface::skynet_sendname(
+                skynet.skynet,
+                0,
+                name.as_ptr(),
+                ptype | interface::PTYPE_TAG_DONTCOPY,
+                session,
+                msg as *const libc::c_void,
+                sz,
+            )
+        }
+    } else {
+        let dest = addr.uint32_value(scope).unwrap_or(0);
+        unsafe {
+            interface::skynet_send(
+                skynet.skynet,
+                0,
+                dest,
+                ptype | interface::PTYPE_TAG_DONTCOPY,
+                session,
+                msg as *const libc::c_void,
+                sz,
+            )
+        }
+    };
+
+    rv.set(v8::Integer::new(scope, session).into());
+}
+
 pub fn op_skynet_socket_send(
     _state: std::cell::RefMut<deno_core::JsRuntimeState>,
     op_state: Rc<RefCell<OpState>>,
@@ -800,8 +1650,17 @@ pub fn op_skynet_socket_send(
     rv: &mut v8::ReturnValue,
 ) {
     let id = get_args!(scope, v8::Integer, args, 1).value();
-    let msg = get_args!(scope, v8::BigInt, args, 2).u64_value().0;
-    let sz = get_args!(scope, v8::Integer, args, 3).value();
+    let rid = message_rid(args.get(2));
+
+    let mut op_state_rc = op_state.borrow_mut();
+    // skynet frees the buffer, even if the send fails
+    let (msg, sz) = match rid.map(|rid| take_message(&mut op_state_rc, rid)) {
+        Some(Ok(message)) => message,
+        _ => {
+            drop(op_state_rc);
+            return throw_bad_message(scope, rid);
+        }
+    };
 
     let mut buffer = interface::socket_sendbuffer {
         id: id as libc::c_int,
@@ -810,7 +1669,6 @@ pub fn op_skynet_socket_send(
         sz: sz as libc::size_t,
     };
 
-    let mut op_state_rc = op_state.borrow_mut();
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
@@ -828,8 +1686,17 @@ pub fn op_skynet_socket_send_lowpriority(
     rv: &mut v8::ReturnValue,
 ) {
     let id = get_args!(scope, v8::Integer, args, 1).value();
-    let msg = get_args!(scope, v8::BigInt, args, 2).u64_value().0;
-    let sz = get_args!(scope, v8::Integer, args, 3).value();
+    let rid = message_rid(args.get(2));
+
+    let mut op_state_rc = op_state.borrow_mut();
+    // skynet frees the buffer, even if the send fails
+    let (msg, sz) = match rid.map(|rid| take_message(&mut op_state_rc, rid)) {
+        Some(Ok(message)) => message,
+        _ => {
+            drop(op_state_rc);
+            return throw_bad_message(scope, rid);
+        }
+    };
 
     let mut buffer = interface::socket_sendbuffer {
         id: id as libc::c_int,
@@ -838,7 +1705,6 @@ pub fn op_skynet_socket_send_lowpriority(
         sz: sz as libc::size_t,
     };
 
-    let mut op_state_rc = op_state.borrow_mut();
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
@@ -858,9 +1724,21 @@ pub fn op_skynet_socket_sendto(
 ) {
     let id = get_args!(scope, v8::Integer, args, 1).value();
     let address = get_args!(scope, v8::String, args, 2).to_rust_string_lossy(scope);
-    let msg = get_args!(scope, v8::BigInt, args, 3).u64_value().0;
-    let sz = get_args!(scope, v8::Integer, args, 4).value();
-    let addr = std::ffi::CString::new(address).unwrap();
+    let rid = message_rid(args.get(3));
+    let addr = match std::ffi::CString::new(address) {
+        Ok(addr) => addr,
+        Err(err) => return throw_type_error(scope, err.to_string()),
+    };
+
+    let mut op_state_rc = op_state.borrow_mut();
+    // skynet frees the buffer, even if the send fails
+    let (msg, sz) = match rid.map(|rid| take_message(&mut op_state_rc, rid)) {
+        Some(Ok(message)) => message,
+        _ => {
+            drop(op_state_rc);
+            return throw_bad_message(scope, rid);
+        }
+    };
 
     let mut buffer = interface::socket_sendbuffer {
         id: id as libc::c_int,
@@ -869,7 +1747,6 @@ pub fn op_skynet_socket_sendto(
         sz: sz as libc::size_t,
     };
 
-    let mut op_state_rc = op_state.borrow_mut();
     let skynet = op_state_rc.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
 
@@ -881,6 +1758,84 @@ pub fn op_skynet_socket_sendto(
     rv.set(v8_ret);
 }
 
+// every view goes out as a raw pointer send, in order. skynet writes it directly when the
+// socket is idle and copies only what is left over, so the views are never merged first.
+// skynet queues them behind earlier sends, a failed send stops the views after it.
+// skynet keeps its socket_server private, so the userobject buffer mode can't be registered
+fn socket_sendv(
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+    lowpriority: bool,
+) {
+    let id = get_args!(scope, v8::Integer, args, 1).value();
+
+    let mut views = Vec::with_capacity(args.length() as usize);
+    for idx in 2..args.length() {
+        match v8::Local::<v8::ArrayBufferView>::try_from(args.get(idx)) {
+            Ok(view) => views.push(ZeroCopyBuf::new(scope, view)),
+            Err(err) => {
+                return throw_type_error(
+                    scope,
+                    format!("Invalid argument at position {}: {}", idx, err),
+                )
+            }
+        }
+    }
+
+    let mut op_state_rc = op_state.borrow_mut();
+    let skynet = op_state_rc.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    let mut err = 0;
+    for view in &views {
+        let view: &[u8] = view;
+        if view.is_empty() {
+            continue;
+        }
+        let mut buffer = interface::socket_sendbuffer {
+            id: id as libc::c_int,
+            msg_type: interface::SOCKET_BUFFER_RAWPOINTER,
+            buffer: view.as_ptr(),
+            sz: view.len(),
+        };
+        err = unsafe {
+            if lowpriority {
+                interface::skynet_socket_sendbuffer_lowpriority(skynet.skynet, &mut buffer)
+            } else {
+                interface::skynet_socket_sendbuffer(skynet.skynet, &mut buffer)
+            }
+        };
+        if err != 0 {
+            break;
+        }
+    }
+
+    let v8_ret = v8::Integer::new(scope, err as i32).into();
+    rv.set(v8_ret);
+}
+
+pub fn op_skynet_socket_sendv(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+) {
+    socket_sendv(op_state, scope, args, rv, false)
+}
+
+pub fn op_skynet_socket_sendv_lowpriority(
+    _state: std::cell::RefMut<deno_core::JsRuntimeState>,
+    op_state: Rc<RefCell<OpState>>,
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: &mut v8::ReturnValue,
+) {
+    socket_sendv(op_state, scope, args, rv, true)
+}
+
 pub fn op_skynet_socket_nodelay(state: &mut OpState, fd: i32, _: ()) -> Result<(), AnyError> {
     let skynet = state.borrow_mut::<SkynetContext>();
     let skynet = unsafe { &mut **skynet };
@@ -888,3 +1843,332 @@ pub fn op_skynet_socket_nodelay(state: &mut OpState, fd: i32, _: ()) -> Result<(
 
     Ok(())
 }
+
+// the socket is paused once this much is buffered and nobody reads it
+const STREAM_PAUSE_SIZE: usize = 1024 * 1024;
+
+// a skynet socket owned by a js Conn, the socket messages of its id are fed here
+// by op_skynet_socket_unpack instead of going to the socket library
+pub struct SocketStreamResource {
+    id: c_int,
+    skynet: *const libc::c_void,
+    pending: RefCell<std::collections::VecDeque<u8>>,
+    paused: Cell<bool>,
+    eof: Cell<bool>,
+    error: RefCell<Option<String>>,
+    closed: Cell<bool>,
+    notify: tokio::sync::Notify,
+}
+
+impl SocketStreamResource {
+    // what skynet already read keeps arriving after the pause, so pending stays near the limit
+    fn push(&self, data: &[u8]) {
+        let mut pending = self.pending.borrow_mut();
+        pending.extend(data);
+        if pending.len() >= STREAM_PAUSE_SIZE && !self.paused.get() && !self.eof.get() {
+            self.paused.set(true);
+            unsafe { interface::skynet_socket_pause(self.skynet, self.id) };
+        }
+        self.notify.notify_waiters();
+    }
+
+    fn finish(&self, error: Option<String>) {
+        self.eof.set(true);
+        *self.error.borrow_mut() = error;
+        self.notify.notify_waiters();
+    }
+
+    // buffered bytes are handed out before eof or the socket error
+    async fn read(&self, buf: &mut [u8]) -> Result<usize, AnyError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            {
+                let mut pending = self.pending.borrow_mut();
+                if !pending.is_empty() {
+                    let n = std::cmp::min(buf.len(), pending.len());
+                    for (dst, src) in buf.iter_mut().zip(pending.drain(..n)) {
+                        *dst = src;
+                    }
+                    if pending.len() < STREAM_PAUSE_SIZE / 2 && self.paused.get() && !self.eof.get() {
+                        self.paused.set(false);
+                        unsafe { interface::skynet_socket_start(self.skynet, self.id) };
+                    }
+                    return Ok(n);
+                }
+            }
+            if self.closed.get() {
+                return Err(custom_error("Interrupted", "socket closed"));
+            }
+            if let Some(err) = self.error.borrow().as_ref() {
+                return Err(custom_error("ConnectionReset", err.clone()));
+            }
+            if self.eof.get() {
+                return Ok(0);
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl Resource for SocketStreamResource {
+    fn name(&self) -> Cow<str> {
+        "skynetSocket".into()
+    }
+
+    fn close(self: Rc<Self>) {
+        self.closed.set(true);
+        self.notify.notify_waiters();
+        // skynet already dropped the socket once it reported close or error
+        if !self.eof.get() {
+            unsafe { interface::skynet_socket_close(self.skynet, self.id) };
+        }
+    }
+}
+
+// hand a socket message to the stream owning its id, returns false if js should dispatch it
+fn feed_stream(
+    skynet: &mut crate::ContextData,
+    op_state: &OpState,
+    socket_message: &interface::skynet_socket_message,
+    errmsg: &[u8],
+) -> bool {
+    let rid = match skynet.streams.get(&socket_message.id) {
+        Some(rid) => *rid,
+        None => return false,
+    };
+    // a closed stream keeps its id until skynet reports the socket gone
+    let stream = op_state.resource_table.get::<SocketStreamResource>(rid);
+
+    match socket_message.msg_type {
+        interface::SKYNET_SOCKET_TYPE_DATA => {
+            if let Some(stream) = stream {
+                stream.push(unsafe {
+                    std::slice::from_raw_parts(socket_message.buffer, socket_message.ud as usize)
+                });
+            }
+            unsafe { interface::skynet_free(socket_message.buffer as *mut libc::c_void) };
+        }
+        interface::SKYNET_SOCKET_TYPE_CLOSE => {
+            skynet.streams.remove(&socket_message.id);
+            if let Some(stream) = stream {
+                stream.finish(None);
+            }
+        }
+        interface::SKYNET_SOCKET_TYPE_ERROR => {
+            skynet.streams.remove(&socket_message.id);
+            if let Some(stream) = stream {
+                unsafe { interface::skynet_socket_shutdown(skynet.skynet, socket_message.id) };
+                stream.finish(Some(String::from_utf8_lossy(errmsg).into_owned()));
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+#[derive(Deserialize)]
+pub struct SocketStreamArgs {
+    id: i32,
+    // skynet already reported the socket closed, only the buffered bytes are left
+    closed: bool,
+}
+
+// move a connected socket id into the resource table, buf holds what the socket library had buffered
+pub fn op_skynet_socket_stream(
+    state: &mut OpState,
+    args: SocketStreamArgs,
+    buf: Option<ZeroCopyBuf>,
+) -> Result<ResourceId, AnyError> {
+    let skynet = state.borrow_mut::<SkynetContext>();
+    let skynet = unsafe { &mut **skynet };
+
+    if let Some(rid) = skynet.streams.get(&args.id) {
+        if state.resource_table.has(*rid) {
+            return Err(custom_error(
+                "AlreadyExists",
+                format!("socket {} is already a stream", args.id),
+            ));
+        }
+    }
+
+    let stream = SocketStreamResource {
+        id: args.id,
+        skynet: skynet.skynet,
+        pending: RefCell::new(buf.as_deref().unwrap_or(&[]).iter().copied().collect()),
+        paused: Cell::new(false),
+        eof: Cell::new(args.closed),
+        error: RefCell::new(None),
+        closed: Cell::new(false),
+        notify: tokio::sync::Notify::new(),
+    };
+    let rid = state.resource_table.add(stream);
+    if !args.closed {
+        skynet.streams.insert(args.id, rid);
+    }
+    Ok(rid)
+}
+
+pub async fn op_skynet_socket_read(
+    state: Rc<RefCell<OpState>>,
+    rid: ResourceId,
+    mut buf: ZeroCopyBuf,
+) -> Result<usize, AnyError> {
+    let stream = state
+        .borrow()
+        .resource_table
+        .get::<SocketStreamResource>(rid)
+        .ok_or_else(bad_resource_id)?;
+    stream.read(&mut buf).await
+}
+
+pub async fn op_skynet_socket_write(
+    state: Rc<RefCell<OpState>>,
+    rid: ResourceId,
+    buf: ZeroCopyBuf,
+) -> Result<usize, AnyError> {
+    let stream = state
+        .borrow()
+        .resource_table
+        .get::<SocketStreamResource>(rid)
+        .ok_or_else(bad_resource_id)?;
+    if stream.eof.get() {
+        return Err(custom_error("BrokenPipe", "socket closed"));
+    }
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    // skynet writes the raw pointer directly or copies it before returning
+    let mut buffer = interface::socket_sendbuffer {
+        id: stream.id,
+        msg_type: interface::SOCKET_BUFFER_RAWPOINTER,
+        buffer: buf.as_ptr(),
+        sz: buf.len(),
+    };
+    let err = unsafe { interface::skynet_socket_sendbuffer(stream.skynet, &mut buffer) };
+    if err != 0 {
+        return Err(custom_error("BrokenPipe", format!("socket {} send failed", stream.id)));
+    }
+    Ok(buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB16: usize = 16 * 1024 * 1024;
+
+    fn context(zero_copy_size: usize, bs_flush_size: usize) -> crate::ContextData {
+        crate::ContextData {
+            skynet: std::ptr::null(),
+            handle: 0,
+            ctx: std::ptr::null(),
+            cb: None,
+            module_search_paths: Vec::new(),
+            inspector: None,
+            bs: None,
+            bs_offset: 0,
+            bs_flag: 0,
+            bs_temp: None,
+            bs_messages: Vec::new(),
+            bs_external: Vec::new(),
+            zero_copy_size,
+            bs_flush_size,
+            calls: std::collections::HashMap::new(),
+            timed_out: std::collections::HashMap::new(),
+            timers: std::collections::HashMap::new(),
+            streams: std::collections::HashMap::new(),
+            resolve_cache: std::collections::HashMap::new(),
+            init_args: Vec::new(),
+            loader_path: String::new(),
+        }
+    }
+
+    // sizes just above the old 16MB limit of the size argument, inline and external
+    #[test]
+    fn batch_round_trips_through_take() {
+        let mut skynet = context(MB16 + 16, 1024 * 1024);
+        // (size, remainder_message, external), a batch ends at remainder 0 or past bs_flush_size
+        let input = [
+            (100, 3, false),
+            (200, 2, false),
+            (MB16 + 1, 1, false),
+            (MB16 + 16, 2, true),
+            (300, 1, false),
+            (0, 0, false),
+            (MB16 + 1, 0, false),
+            (MB16 + 17, 1, true),
+            (MB16 + 2, 0, false),
+        ];
+        // external messages are never read, the pointer only has to come back
+        let fake_ptr = |i: usize| (0x7f00_0000_0000 + i * 0x1000) as *const u8;
+
+        let mut pending = Vec::new();
+        let mut batches = Vec::new();
+        for (i, &(sz, remainder, external)) in input.iter().enumerate() {
+            let data = if external { Vec::new() } else { vec![i as u8 + 1; sz] };
+            let msg = if external { fake_ptr(i) } else { data.as_ptr() };
+            let (owned, full) = batch_message(
+                &mut skynet,
+                interface::PTYPE_TEXT,
+                i as c_int + 1,
+                0x0100_0000 + i as c_int,
+                msg,
+                sz,
+                remainder,
+            );
+            assert_eq!(owned, external);
+            pending.push((i, msg as u64));
+            if !full {
+                continue;
+            }
+
+            let batch = take_batch(&mut skynet);
+            assert_eq!(skynet.bs_offset, 0);
+            // the size reaches js as a number, like flush passes it
+            let msgsz = batch.sz as f64;
+            assert_eq!(msgsz as usize, batch.sz);
+            let buf = unsafe {
+                let bs = skynet.bs.as_ref().unwrap();
+                get_backing_store_slice_mut(bs, 0, bs.byte_length())
+            };
+
+            let mut offset = 0;
+            let mut externals = 0;
+            for (n, &(i, msg)) in pending.iter().enumerate() {
+                let (sz, _, external) = input[i];
+                let head = &buf[offset..offset + MESSAGE_HEAD_SZ];
+                assert_eq!(LittleEndian::read_i32(&head[0..]), interface::PTYPE_TEXT);
+                assert_eq!(LittleEndian::read_i32(&head[4..]), i as c_int + 1);
+                assert_eq!(LittleEndian::read_i32(&head[8..]), 0x0100_0000 + i as c_int);
+                assert_eq!(LittleEndian::read_u64(&head[16..]), sz as u64);
+                assert_eq!(LittleEndian::read_u64(&head[24..]), msg);
+                assert_eq!(batch.messages[n], (interface::PTYPE_TEXT, i as c_int + 1, 0x0100_0000 + i as c_int));
+                offset += MESSAGE_HEAD_SZ;
+                if external {
+                    externals += 1;
+                    assert_eq!(LittleEndian::read_u32(&head[12..]), externals);
+                    let bs = &batch.externals[externals as usize - 1];
+                    assert_eq!(bs.byte_length(), sz);
+                    assert_eq!(bs.data() as u64, msg);
+                } else {
+                    assert_eq!(LittleEndian::read_u32(&head[12..]), 0);
+                    assert!(buf[offset..offset + sz].iter().all(|b| *b == i as u8 + 1));
+                    offset += sz;
+                }
+            }
+            assert_eq!(offset, batch.sz);
+            assert_eq!(batch.externals.len(), externals as usize);
+            batches.push((batch.messages.len(), batch.new_bs));
+            // the fake pointers must not reach skynet_free
+            std::mem::forget(batch.externals);
+            pending.clear();
+        }
+
+        assert!(pending.is_empty());
+        // the buffer grows for the first and the 16MB message of the first batch, reported once
+        assert_eq!(batches, vec![(3, true), (3, false), (1, false), (2, false)]);
+    }
+}
@@ -109,7 +109,10 @@ pub fn op_tls_set_cert(
 
     let ctx = unsafe { &mut *(ctx as *mut SslCtx) };
 
-    let certfile = std::ffi::CString::new(certfile).unwrap();
+    let certfile = match std::ffi::CString::new(certfile) {
+        Ok(certfile) => certfile,
+        Err(err) => return throw_error!(scope, err.to_string()),
+    };
     let mut ret = unsafe { SSL_CTX_use_certificate_chain_file(ctx.ctx, certfile.as_ptr()) };
     if ret != 1 {
         return throw_error!(
@@ -118,7 +121,10 @@ pub fn op_tls_set_cert(
         );
     }
 
-    let keyfile = std::ffi::CString::new(keyfile).unwrap();
+    let keyfile = match std::ffi::CString::new(keyfile) {
+        Ok(keyfile) => keyfile,
+        Err(err) => return throw_error!(scope, err.to_string()),
+    };
     ret = unsafe { SSL_CTX_use_PrivateKey_file(ctx.ctx, keyfile.as_ptr(), SSL_FILETYPE_PEM) };
     if ret != 1 {
         return throw_error!(scope, format!("SSL_CTX_use_PrivateKey_file error: {}", ret));
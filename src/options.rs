@@ -0,0 +1,108 @@
+use libc::c_void;
+use std::collections::HashMap;
+
+// launch options: `--key=value` before the service name, they override the skynet env
+// e.g. skynet.newservice("snjs", "--js_heap_limit=256", "agent")
+pub struct ServiceOptions {
+    skynet: *const c_void,
+    overrides: HashMap<String, String>,
+}
+
+impl ServiceOptions {
+    pub fn parse(skynet: *const c_void, args: &str) -> (ServiceOptions, String) {
+        let mut overrides = HashMap::new();
+        let mut params = args.split_whitespace().peekable();
+        while let Some(param) = params.peek() {
+            if !param.starts_with("--") {
+                break;
+            }
+            let option = &param[2..];
+            match option.find('=') {
+                Some(pos) => overrides.insert(option[..pos].to_owned(), option[pos + 1..].to_owned()),
+                None => overrides.insert(option.to_owned(), String::from("true")),
+            };
+            params.next();
+        }
+
+        let args = params.collect::<Vec<&str>>().join(" ");
+        (ServiceOptions { skynet, overrides }, args)
+    }
+
+    // the skynet env without this service's launch overrides, for state shared by every service
+    pub fn env_only(&self) -> ServiceOptions {
+        ServiceOptions {
+            skynet: self.skynet,
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str, default: &str) -> String {
+        match self.overrides.get(name) {
+            Some(value) => value.clone(),
+            None => crate::get_env(self.skynet, name, default),
+        }
+    }
+
+    // "true": allow all, "false": deny, otherwise a `,` separated allow list.
+    // deno treats an empty list as allow all, so a list without entries denies
+    fn parse_allow_list(value: &str) -> Option<Vec<String>> {
+        match value.trim() {
+            "true" => Some(Vec::new()),
+            "false" | "" => None,
+            list => {
+                let list: Vec<String> = list
+                    .split(',')
+                    .map(|item| item.trim().to_owned())
+                    .filter(|item| !item.is_empty())
+                    .collect();
+                if list.is_empty() {
+                    None
+                } else {
+                    Some(list)
+                }
+            }
+        }
+    }
+
+    // permissions come from the skynet env, a launch override can only narrow them
+    pub fn get_allow_list(&self, name: &str, default: &str) -> Option<Vec<String>> {
+        let allowed = Self::parse_allow_list(&crate::get_env(self.skynet, name, default))?;
+        let requested = match self.overrides.get(name) {
+            Some(value) => Self::parse_allow_list(value)?,
+            None => return Some(allowed),
+        };
+        if allowed.is_empty() {
+            Some(requested)
+        } else if requested.is_empty() {
+            Some(allowed)
+        } else {
+            let list: Vec<String> = requested
+                .into_iter()
+                .filter(|item| allowed.contains(item))
+                .collect();
+            // nothing in common must deny, not fall back to allow all
+            if list.is_empty() {
+                None
+            } else {
+                Some(list)
+            }
+        }
+    }
+
+    pub fn get_allow_bool(&self, name: &str, default: bool) -> bool {
+        let parse = |value: &str, default: bool| match value.trim() {
+            "true" => true,
+            "false" => false,
+            _ => default,
+        };
+        let allowed = parse(&crate::get_env(self.skynet, name, ""), default);
+        match self.overrides.get(name) {
+            Some(value) => allowed && parse(value, allowed),
+            None => allowed,
+        }
+    }
+
+    pub fn get_usize(&self, name: &str, default: usize) -> usize {
+        self.get(name, "").trim().parse().unwrap_or(default)
+    }
+}
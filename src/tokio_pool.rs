@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use deno_core::serde::Serialize;
+
+use crate::options::ServiceOptions;
+
+#[derive(Default)]
+struct Counters {
+    threads: AtomicUsize,
+    parked: AtomicUsize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokioStats {
+    dedicated: bool,
+    worker_threads: usize,
+    max_blocking_threads: usize,
+    threads: usize,
+    parked_threads: usize,
+}
+
+pub struct TokioPool {
+    runtime: tokio::runtime::Runtime,
+    counters: Arc<Counters>,
+    dedicated: bool,
+    worker_threads: usize,
+    max_blocking_threads: usize,
+}
+
+impl TokioPool {
+    // js_tokio_worker_threads, js_tokio_blocking_threads, js_tokio_stack_size(bytes), js_tokio_thread_name
+    pub fn new(options: &ServiceOptions, dedicated: usize) -> TokioPool {
+        let worker_threads = if dedicated > 0 {
+            dedicated
+        } else {
+            options.get_usize("js_tokio_worker_threads", 4)
+        };
+        let max_blocking_threads = options.get_usize("js_tokio_blocking_threads", 512);
+        let stack_size = options.get_usize("js_tokio_stack_size", 0);
+        let mut thread_name = options.get("js_tokio_thread_name", "tokio-pool");
+        if dedicated > 0 {
+            thread_name = thread_name + "-dedicated";
+        }
+
+        let counters = Arc::new(Counters::default());
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .worker_threads(worker_threads.max(1))
+            .max_blocking_threads(max_blocking_threads.max(1))
+            .thread_name(thread_name);
+        if stack_size > 0 {
+            builder.thread_stack_size(stack_size);
+        }
+        {
+            let counters = counters.clone();
+            builder.on_thread_start(move || {
+                counters.threads.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        {
+            let counters = counters.clone();
+            builder.on_thread_stop(move || {
+                counters.threads.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+        {
+            let counters = counters.clone();
+            builder.on_thread_park(move || {
+                counters.parked.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        {
+            let counters = counters.clone();
+            builder.on_thread_unpark(move || {
+                counters.parked.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+
+        TokioPool {
+            runtime: builder.build().unwrap(),
+            counters,
+            dedicated: dedicated > 0,
+            worker_threads: worker_threads.max(1),
+            max_blocking_threads: max_blocking_threads.max(1),
+        }
+    }
+
+    pub fn enter(&self) -> tokio::runtime::EnterGuard<'_> {
+        self.runtime.enter()
+    }
+
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn is_dedicated(&self) -> bool {
+        self.dedicated
+    }
+
+    pub fn shutdown(self) {
+        self.runtime.shutdown_background();
+    }
+
+    pub fn stats(&self) -> TokioStats {
+        TokioStats {
+            dedicated: self.dedicated,
+            worker_threads: self.worker_threads,
+            max_blocking_threads: self.max_blocking_threads,
+            threads: self.counters.threads.load(Ordering::Relaxed),
+            parked_threads: self.counters.parked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static mut SHARED_POOL: *mut TokioPool = std::ptr::null_mut();
+
+// js_tokio_dedicated=<threads> gives the service its own runtime, otherwise all services share one.
+// the shared one is sized by the skynet env only, launch overrides apply to dedicated runtimes
+pub fn get(options: &ServiceOptions) -> *mut TokioPool {
+    let dedicated = options.get_usize("js_tokio_dedicated", 0);
+    if dedicated > 0 {
+        return Box::into_raw(Box::new(TokioPool::new(options, dedicated)));
+    }
+
+    static SHARED_INIT: std::sync::Once = std::sync::Once::new();
+    SHARED_INIT.call_once(|| {
+        let pool = Box::new(TokioPool::new(&options.env_only(), 0));
+        unsafe { SHARED_POOL = Box::into_raw(pool) };
+    });
+    unsafe { SHARED_POOL }
+}
@@ -1,7 +1,6 @@
 declare namespace Deno {
     function v8MemoryInfo(): any;
 }
-declare const JS_INIT_ARGS: string;
 declare const Skynet: any;
 declare const SkynetTls: any;
 declare const V8Inspector: any;
@@ -1,4 +1,4 @@
-let args = JS_INIT_ARGS.split(" ");
+let args: string[] = Skynet.init_args();
 var SERVICE_NAME = args[0];
 let skynet = Skynet;
 
@@ -24,9 +24,9 @@ skynet.register_protocol({
     id: skynet.PTYPE_ID.DEBUG,
     name: skynet.PTYPE_NAME.DEBUG,
     pack: lua_seri.encode,
-    unpack: (msg: Uint8Array, offset: number, sz: number) => {
-        return lua_seri.decode_ex(msg, offset, sz);
-    },
+    pack_msg: Skynet.lua_pack,
+    unpack: Skynet.lua_unpack,
+    unpack_msg: (msg: skynet.MESSAGE) => Skynet.lua_unpack(msg),
     dispatch: _debug_dispatch,
 })
 
@@ -32,9 +32,16 @@ type PROTOCOL_TYPE = {
     name: string,
     id: number,
     pack?: (...obj: any) => Uint8Array,
+    pack_msg?: (...obj: any) => MESSAGE,    // pack into a skynet message
     unpack?: (buf: Uint8Array, offset: number, sz: number) => any[],
+    unpack_msg?: (msg: MESSAGE) => any[],   // unpack a skynet message in place
     dispatch?: Function,
 };
+// a skynet message handle, consumed when it is sent
+export type MESSAGE = {
+    readonly rid: number,
+    readonly sz: number,
+};
 export type CONTEXT = {
     proto: PROTOCOL_TYPE,
     session: number,
@@ -47,7 +54,6 @@ export type SERVICE_ADDR = number | string;    // service_addr|service_name
 
 let session_id_callback = new Map<number, [Function, Function, boolean?]>();  // session -> [resolve, reject, wakeup]
 let watching_response = new Map<number, SERVICE_ADDR>();     // session -> addr
-let watching_request = new Map<number, SERVICE_ADDR>();     // session -> addr
 let unresponse = new Map<Function, SERVICE_ADDR>();   // call session -> [addr, reject]
 let sleep_session = new Map<number, number>();  // token -> session
 let next_dispatch_id = 1;
@@ -86,13 +92,6 @@ function _error_dispatch(error_session: number, error_source: SERVICE_ADDR) {
                 unresponse.delete(resp);
             }
         }
-        
-        for (let [session, addr] of watching_request) {
-            if (addr == error_source) {
-                watching_request.delete(session);
-                _error_dispatch(session, error_source);
-            }
-        }
     } else {
         // capture an error for error_session
         ignoreret(error_session);
@@ -162,28 +161,58 @@ export function get_cur_msgptr(): [number, number] {
     return [cur_msgptr_1, cur_msgptr_2];
 }
 let message_head_sz = 32;
-async function dispatch_message(msgsz: number) {
-    let is_new_bs = (msgsz >> 24) != 0;
-    msgsz = msgsz & 0xffffff;
-    let shared_buf = get_shared_bs(is_new_bs);
-    let offset = 0;
-
-    while (offset+message_head_sz <= msgsz) {
-        let prototype = pack.decode_uint32(shared_buf, offset+0, true);
-        let session = pack.decode_uint32(shared_buf, offset+4, true);
-        let source = pack.decode_uint32(shared_buf, offset+8, true);
-        let sz = pack.decode_uint32(shared_buf, offset+12, true);
-        cur_msgptr_1 = 0;
-        cur_msgptr_2 = 0;
-        pack.decode_rawbuf(shared_buf, offset+16, 8, cur_msgptr);
-
-        try {
-            dispatch_message_impl(prototype, session, source, sz, shared_buf, offset + message_head_sz)
-        } catch(e) {
-            skynet_rt.error(`dispatch message error:${e}`)
+export type MESSAGE_HEAD = {
+    prototype: number,
+    session: number,
+    source: number,
+    external: number,   // 1-based index into the externals of the batch, 0: the data follows the head
+    sz: number,
+};
+// the head written by the native batch_message: type:i32 session:i32 source:i32 external:u32 sz:u64 msg:u64
+export function decode_message_head(shared_buf: Uint8Array, offset: number): MESSAGE_HEAD {
+    return {
+        prototype: pack.decode_uint32(shared_buf, offset+0, true),
+        session: pack.decode_uint32(shared_buf, offset+4, true),
+        source: pack.decode_uint32(shared_buf, offset+8, true),
+        external: pack.decode_uint32(shared_buf, offset+12, true),
+        sz: pack.decode_uint32(shared_buf, offset+16, true) + pack.decode_uint32(shared_buf, offset+20, true) * 0x100000000,
+    };
+}
+// called synchronously from the native flush, each message handles its own errors.
+// returns how many messages were dispatched, the native side fails the request sessions of the rest
+function dispatch_message(msgsz: number, is_new_bs: boolean, externals?: ArrayBuffer[]): number {
+    let count = 0;
+    try {
+        let shared_buf = get_shared_bs(is_new_bs);
+        let offset = 0;
+
+        while (offset+message_head_sz <= msgsz) {
+            let { prototype, session, source, external, sz } = decode_message_head(shared_buf, offset);
+            cur_msgptr_1 = 0;
+            cur_msgptr_2 = 0;
+            pack.decode_rawbuf(shared_buf, offset+24, 8, cur_msgptr);
+
+            let buf = external ? new Uint8Array(externals![external - 1]) : shared_buf;
+            let buf_offset = external ? 0 : offset + message_head_sz;
+            // the message is unpacked before the first await, so the shared buffer may be reused afterwards
+            dispatch_message_impl(prototype, session, source, sz, buf, buf_offset)
+                .catch((e) => _dispatch_error(prototype, session, source, e));
+            count++;
+
+            offset = offset + message_head_sz + (external ? 0 : sz)
         }
+    } catch (e) {
+        skynet_rt.error(`dispatch message error:${e && e.stack || e}`);
+    }
+    return count;
+}
 
-        offset = offset + message_head_sz + sz
+// only the request that failed gets an error, and only if nobody responded to it yet
+function _dispatch_error(prototype: number, session: number, source: number, e: any) {
+    skynet_rt.error(`dispatch message error type:${prototype} session:${session} source:${source} ${e && e.stack || e}`);
+    if (session && prototype != PTYPE_ID.RESPONSE && prototype != PTYPE_ID.ERROR && watching_response.get(session) === source) {
+        watching_response.delete(session);
+        skynet_rt.send(source, PTYPE_ID.ERROR, session);
     }
 }
 
@@ -221,22 +250,21 @@ async function dispatch_message_impl(prototype: number, session: number, source:
 }
 
 export function timeout(ti: number, func: Function) {
-    let session = Number(skynet_rt.command("TIMEOUT", ti));
-    assert(session);
-    assert(!session_id_callback.has(session));
-    session_id_callback.set(session, [func, func]);
+    let session = skynet_rt.timeout(ti);
+    skynet_rt.sleep(session).then(() => func()).catch((e: any) => {
+        skynet_rt.error(`timeout func error:${e}`);
+    });
 }
 
 export async function sleep(ti: number, token?: number) {
     token = token || gen_token();
-    let session = Number(skynet_rt.command("TIMEOUT", ti));
+    let session = skynet_rt.timeout(ti);
     sleep_session.set(token, session);
-
-    let promise = new Promise((resolve, reject) => {
-        session_id_callback.set(session, [resolve, reject]);
-    })
-    await promise;
-    sleep_session.delete(token);
+    try {
+        await skynet_rt.sleep(session);
+    } finally {
+        sleep_session.delete(token);
+    }
 }
 
 export async function wait(token: number) {
@@ -254,6 +282,9 @@ export async function wait(token: number) {
 export function wakeup(token: number) {
     let session = sleep_session.get(token);
     assert(session);
+    if (skynet_rt.wakeup(session)) {
+        return;
+    }
     let response_func = session_id_callback.get(session!);
     assert(response_func);
     response_func![0]();
@@ -292,16 +323,7 @@ export function exit() {
     }
     unresponse.clear();
 
-    // report the sources I call but haven't return
-    let watching_addr = new Set<SERVICE_ADDR>();
-    for (let [_, addr] of watching_request) {
-        watching_addr.add(addr);
-    }
-    watching_request.clear();
-    watching_addr.forEach((addr) => {
-        skynet_rt.send(addr, PTYPE_ID.ERROR, 0);
-    });
-
+    // the sources I call but haven't return are reported on release with the pending calls
     skynet_rt.exit();
 }
 
@@ -314,10 +336,17 @@ export function set_env(name: string, value: string) {
     skynet_rt.set_env(name, value)
 }
 
+function send_packed(addr: SERVICE_ADDR, p: PROTOCOL_TYPE, ptype: number, session: number|null, params: any[]) {
+    if (p.pack_msg) {
+        return skynet_rt.send_msg(addr, ptype, session, p.pack_msg(...params));
+    }
+    let pack = p.pack!(...params);
+    return skynet_rt.send(addr, ptype, session, pack);
+}
+
 export function send(addr: SERVICE_ADDR, typename: string, ...params: any[]): number {
     let p = proto.get(typename);
-    let pack = p!.pack!(...params);
-    return skynet_rt.send(addr, p!.id, 0, pack);
+    return send_packed(addr, p!, p!.id, 0, params);
 }
 
 export function rawsend(addr: SERVICE_ADDR, typename: string, bytes: Uint8Array): number {
@@ -331,46 +360,44 @@ export function genid(): number {
 
 // TODO skynet.redirect
 
-async function _yield_call(session: number, addr: SERVICE_ADDR): Promise<[Uint8Array, number, number]> {
-    let promise = new Promise((resolve, reject) => {
-        session_id_callback.set(session, [resolve, reject]);
-    })
-
-    watching_request.set(session, addr);
-    try {
-        let rsp = (await promise) as [Uint8Array, number, number];
-        return rsp;
-    } catch {
-        throw new Error("call failed");
-    } finally {
-        watching_request.delete(session);
-        session_id_callback.delete(session);    
-    }
-}
-
 export type CALL_OPTIONS = {
     typename: string,
     ret_type?: string,
+    timeout?: number,   // ms, the call fails once it passes
 };
 export async function call(addr: SERVICE_ADDR, typename: string, ...params: any[]): Promise<any[]>;
 export async function call(addr: SERVICE_ADDR, ops: CALL_OPTIONS, ...params: any[]): Promise<any[]>;
 export async function call(addr: SERVICE_ADDR, typename: string|CALL_OPTIONS, ...params: any[]) {
     let call_typename: string;
     let ret_typename: string;
+    let timeout = 0;
     if (typeof(typename) == "string") {
         call_typename = typename;
         ret_typename = typename;
     } else {
         call_typename = typename.typename;
         ret_typename = typename.ret_type || typename.typename;
+        timeout = typename.timeout || 0;
+    }
+    let p = proto.get(call_typename)!;
+    let msg: MESSAGE;
+    if (p.pack_msg) {
+        msg = await skynet_rt.call_msg(addr, p.id, timeout, p.pack_msg(...params));
+    } else {
+        msg = await skynet_rt.call(addr, p.id, timeout, p.pack!(...params));
     }
-    let p = proto.get(call_typename);
-    let pack = p!.pack!(...params);
-    let session = skynet_rt.send(addr, p!.id, null, pack);
-    let [buff, offset, sz] = await _yield_call(session, addr);
 
-    let ret_p = proto.get(ret_typename);
-    return ret_p!.unpack!(buff, offset, sz);
+    let ret_p = proto.get(ret_typename)!;
+    try {
+        if (ret_p.unpack_msg) {
+            return ret_p.unpack_msg(msg);
+        }
+        let buff = new Uint8Array(msg.sz);
+        skynet_rt.fetch_message(msg, msg.sz, buff.buffer, 0);
+        return ret_p.unpack!(buff, 0, msg.sz);
+    } finally {
+        skynet_rt.free(msg);
+    }
 }
 
 export function ret(context: CONTEXT, pack?: Uint8Array) {
@@ -396,8 +423,23 @@ export function ret(context: CONTEXT, pack?: Uint8Array) {
 }
 
 export function retpack(context: CONTEXT, ...params: any) {
-    let pack = context.proto!.pack!(...params);
-    return ret(context, pack);
+    if (context.session == 0) {
+        // send don't need ret
+        return false;
+    }
+    if (!context.proto!.pack_msg) {
+        let pack = context.proto!.pack!(...params);
+        return ret(context, pack);
+    }
+
+    watching_response.delete(context.session);
+    let ret_session = send_packed(context.source, context.proto!, PTYPE_ID.RESPONSE, context.session, params);
+    if (ret_session) {
+        return true;
+    } else if (ret_session === false) {
+        skynet_rt.send(context.source, PTYPE_ID.ERROR, context.session);
+    }
+    return false;
 }
 
 export function response(context: CONTEXT) {
@@ -416,8 +458,7 @@ export function response(context: CONTEXT) {
         let ret = false;
         if (unresponse.has(response)) {
             if (ok) {
-                let pack = context.proto!.pack!(...params);
-                ret = skynet_rt.send(context.source, PTYPE_ID.RESPONSE, context.session, pack);
+                ret = send_packed(context.source, context.proto!, PTYPE_ID.RESPONSE, context.session, params);
                 if (ret == false) {
 					// If the package is too large, returns false. so we should report error back
 					skynet_rt.send(context.source, PTYPE_ID.ERROR, context.session)
@@ -498,6 +539,7 @@ export function error(msg: string) {
 }
 
 import * as lua_seri from "lua_seri"
+skynet_rt.lua_userdata(lua_seri.UserData);
 register_protocol({
     id: PTYPE_ID.LUA,
     name: PTYPE_NAME.LUA,
@@ -507,9 +549,9 @@ register_protocol({
         [shared_bytes, sz] = lua_seri.encode_ex(bytes, 0, ...obj);
         return shared_bytes.subarray(0, sz);
     },
-    unpack: (msg: Uint8Array, offset: number, sz: number) => {
-        return lua_seri.decode_ex(msg, offset, sz);
-    },
+    pack_msg: skynet_rt.lua_pack,
+    unpack: skynet_rt.lua_unpack,
+    unpack_msg: (msg: MESSAGE) => skynet_rt.lua_unpack(msg),
     dispatch: undefined,
 })
 
@@ -635,6 +677,25 @@ export function memory_info() {
     };
 }
 
+export type TOKIO_STATS = {
+    dedicated: boolean,
+    workerThreads: number,
+    maxBlockingThreads: number,
+    threads: number,
+    parkedThreads: number,
+};
+export function tokio_stats(): TOKIO_STATS {
+    return skynet_rt.tokio_stats();
+}
+
+export type WAKE_STATS = {
+    sent: number,       // wake messages queued to the service
+    suppressed: number, // wakes coalesced into an already queued message
+};
+export function wake_stats(): WAKE_STATS {
+    return skynet_rt.wake_stats();
+}
+
 export function task() {
     return session_id_callback.size;    
 }
@@ -0,0 +1,111 @@
+import * as socket from "skynet/socket"
+let skynet_rt = Skynet;
+
+const STREAM_CHUNK_SIZE = 16 * 1024;
+
+// a skynet tcp socket behind the Deno.Conn interface, for libraries written against Deno.connect
+export class Conn implements Deno.Conn {
+    readonly rid: number;
+    readonly id: socket.SOCKET_ID;
+    readonly localAddr: Deno.NetAddr;
+    readonly remoteAddr: Deno.NetAddr;
+    #closed = false;
+    #readable?: ReadableStream<Uint8Array>;
+    #writable?: WritableStream<Uint8Array>;
+
+    constructor(rid: number, id: socket.SOCKET_ID, remote: Deno.NetAddr) {
+        this.rid = rid;
+        this.id = id;
+        this.remoteAddr = remote;
+        // skynet doesn't report the local address of a socket
+        this.localAddr = { transport: "tcp", hostname: "0.0.0.0", port: 0 };
+    }
+
+    async read(p: Uint8Array): Promise<number | null> {
+        if (p.length == 0) {
+            return 0;
+        }
+        let n = await skynet_rt.socket_read(this.rid, p);
+        return n == 0 ? null : n;
+    }
+
+    async write(p: Uint8Array): Promise<number> {
+        return await skynet_rt.socket_write(this.rid, p);
+    }
+
+    close() {
+        if (this.#closed) {
+            return;
+        }
+        this.#closed = true;
+        Deno.close(this.rid);
+    }
+
+    // skynet has no half close, closing would stop the reads as well
+    async closeWrite() {
+        throw new Deno.errors.NotSupported("skynet sockets can't close only the write side");
+    }
+
+    get readable(): ReadableStream<Uint8Array> {
+        if (!this.#readable) {
+            this.#readable = new ReadableStream<Uint8Array>({
+                pull: async (controller) => {
+                    let chunk = new Uint8Array(STREAM_CHUNK_SIZE);
+                    try {
+                        let n = await this.read(chunk);
+                        if (n === null) {
+                            this.close();
+                            controller.close();
+                            return;
+                        }
+                        controller.enqueue(chunk.subarray(0, n));
+                    } catch (e) {
+                        controller.error(e);
+                        this.close();
+                    }
+                },
+                cancel: () => {
+                    this.close();
+                },
+            });
+        }
+        return this.#readable;
+    }
+
+    get writable(): WritableStream<Uint8Array> {
+        if (!this.#writable) {
+            this.#writable = new WritableStream<Uint8Array>({
+                write: async (chunk) => {
+                    await this.write(chunk);
+                },
+                close: () => {
+                    this.close();
+                },
+                abort: () => {
+                    this.close();
+                },
+            });
+        }
+        return this.#writable;
+    }
+}
+
+function _net_addr(address: string | undefined, hostname: string, port: number): Deno.NetAddr {
+    let r;
+    if (address && (r = address.match(/^\[(.*)\]:(\d+)$/) || address.match(/^([^:]+):(\d+)$/))) {
+        return { transport: "tcp", hostname: r[1], port: Number(r[2]) };
+    }
+    return { transport: "tcp", hostname: address || hostname, port };
+}
+
+// take over a socket from socket.open or socket.start, address is what the accept callback got
+export function from_socket(id: socket.SOCKET_ID, address?: string): Conn {
+    let [rid, connected_addr] = socket.into_stream(id);
+    return new Conn(rid, id, _net_addr(address || connected_addr, "", 0));
+}
+
+export async function connect(hostname: string, port: number): Promise<Conn> {
+    let id = await socket.open(hostname, port);
+    let [rid, connected_addr] = socket.into_stream(id);
+    return new Conn(rid, id, _net_addr(connected_addr, hostname, port));
+}
@@ -12,7 +12,7 @@ export type SOCKET_ID = number;
 type SOCKET_UDP_RECV = (data: Uint8Array, sz: number, address: string) => void;
 type SOCKET_ACCEPT_CB = (accept_id: SOCKET_ID, address: string) => void;
 type SOCKET_WARNING_CB = (id: SOCKET_ID, size: number) => void;
-type MSGPTR = bigint;
+type MSGPTR = skynet.MESSAGE;
 type SOCKET = {
     protocol: PROTOCOL_TYPE,
     id: SOCKET_ID,
@@ -28,6 +28,7 @@ type SOCKET = {
     on_warning?: SOCKET_WARNING_CB,
     lock?: Array<number>,
     buffer_limit?: number,
+    address?: string,
 }
 
 // socket api
@@ -88,6 +89,18 @@ export async function close(id: SOCKET_ID) {
     socket_pool.delete(id);
 }
 
+// hand a started socket over to a resource stream, the buffered data goes with it
+export function into_stream(id: SOCKET_ID): [number, string?] {
+    let s = socket_pool.get(id);
+    skynet.assert(s && s.buffer, `socket ${id} is not a started tcp socket`);
+    skynet.assert(!s!.suspend_token && (!s!.lock || !s!.lock.length), `socket ${id} is busy`);
+
+    let [buffered] = _read_all(s!.buffer!);
+    let rid = skynet_rt.socket_stream(id, !s!.connected, buffered);
+    socket_pool.delete(id);
+    return [rid, s!.address];
+}
+
 export async function block(id: SOCKET_ID) {
     let s = socket_pool.get(id);
     if (!s || !s.connected) {
@@ -310,10 +323,10 @@ export async function readline(id: SOCKET_ID, sep: string = '\n', buffer?: Uint8
 export type BUFFER_TYPE = Uint8Array|MSGPTR|Uint8Array[]|string;
 
 let text_encoder = new TextEncoder();
-function get_buffer(buffer: BUFFER_TYPE, sz?: number): [MSGPTR, number] {
+function get_buffer(buffer: BUFFER_TYPE): MSGPTR {
     let t = typeof(buffer);
-    if (t == "bigint") {
-        return [buffer as MSGPTR, sz!];
+    if (buffer instanceof skynet_rt.Message) {
+        return buffer as MSGPTR;
     } else if (t == "string") {
         return skynet_rt.alloc_msg(text_encoder.encode(buffer as string));
     } else if (buffer instanceof Uint8Array) {
@@ -323,33 +336,46 @@ function get_buffer(buffer: BUFFER_TYPE, sz?: number): [MSGPTR, number] {
     }
 }
 
-export function write(id: SOCKET_ID, buffer: MSGPTR, sz: number): boolean;
+export function write(id: SOCKET_ID, buffer: MSGPTR): boolean;
 export function write(id: SOCKET_ID, buffer: Uint8Array): boolean;
 export function write(id: SOCKET_ID, buffer: Uint8Array[]): boolean;
 export function write(id: SOCKET_ID, buffer: string): boolean;
-export function write(id: SOCKET_ID, buffer: BUFFER_TYPE, sz?: number): boolean {
-    let [msg, len] = get_buffer(buffer, sz);
-    let err = skynet_rt.socket_send(id, msg, len);
+export function write(id: SOCKET_ID, buffer: BUFFER_TYPE): boolean {
+    let err;
+    if (buffer instanceof Uint8Array) {
+        err = skynet_rt.socket_sendv(id, buffer);
+    } else if (Array.isArray(buffer)) {
+        // header and body go out without being merged first
+        err = skynet_rt.socket_sendv(id, ...buffer);
+    } else {
+        err = skynet_rt.socket_send(id, get_buffer(buffer));
+    }
     return !err;
 }
 
-export function lwrite(id: SOCKET_ID, buffer: MSGPTR, sz: number): boolean;
+export function lwrite(id: SOCKET_ID, buffer: MSGPTR): boolean;
 export function lwrite(id: SOCKET_ID, buffer: Uint8Array): boolean;
 export function lwrite(id: SOCKET_ID, buffer: Uint8Array[]): boolean;
 export function lwrite(id: SOCKET_ID, buffer: string): boolean;
-export function lwrite(id: SOCKET_ID, buffer: BUFFER_TYPE, sz?: number): boolean {
-    let [msg, len] = get_buffer(buffer, sz);
-    let err = skynet_rt.socket_send_lowpriority(id, msg, len);
+export function lwrite(id: SOCKET_ID, buffer: BUFFER_TYPE): boolean {
+    let err;
+    if (buffer instanceof Uint8Array) {
+        err = skynet_rt.socket_sendv_lowpriority(id, buffer);
+    } else if (Array.isArray(buffer)) {
+        err = skynet_rt.socket_sendv_lowpriority(id, ...buffer);
+    } else {
+        err = skynet_rt.socket_send_lowpriority(id, get_buffer(buffer));
+    }
     return !err;
 }
 
-export function sendto(id: SOCKET_ID, address: string, buffer: MSGPTR, sz: number): boolean;
+export function sendto(id: SOCKET_ID, address: string, buffer: MSGPTR): boolean;
 export function sendto(id: SOCKET_ID, address: string, buffer: Uint8Array): boolean;
 export function sendto(id: SOCKET_ID, address: string, buffer: Uint8Array[]): boolean;
 export function sendto(id: SOCKET_ID, address: string, buffer: string): boolean;
-export function sendto(id: SOCKET_ID, address: string, buffer: BUFFER_TYPE, sz?: number): boolean {
-    let [msg, len] = get_buffer(buffer, sz);
-    let err = skynet_rt.socket_sendto(id, address, msg, len);
+export function sendto(id: SOCKET_ID, address: string, buffer: BUFFER_TYPE): boolean {
+    let msg = get_buffer(buffer);
+    let err = skynet_rt.socket_sendto(id, address, msg);
     return !err;
 }
 
@@ -422,7 +448,7 @@ socket_message[SKYNET_SOCKET_TYPE_CONNECT] = (id: SOCKET_ID, _ud: number, buffer
     }
 
     let [addr] = pack.decode_str(buffer, offset, 2, true);
-    // log remote addr
+    s.address = addr;
     s.connected = true;
     wakeup(s);
 }
@@ -489,7 +515,11 @@ skynet.register_protocol({
         let [ptr1, ptr2] = skynet.get_cur_msgptr();
         return [skynet_rt.socket_unpack(ptr1, ptr2, sz)];
     },
-    dispatch: (context: skynet.CONTEXT, is_new_bs: boolean) => {
+    dispatch: (context: skynet.CONTEXT, is_new_bs: boolean | null) => {
+        if (is_new_bs === null) {
+            // fed to a socket stream
+            return;
+        }
         let buffer = skynet.get_shared_bs_temp(is_new_bs);
         let offset = 0;
         let type = pack.decode_uint32(buffer, offset, true); offset += 4;
@@ -0,0 +1,65 @@
+import * as skynet from "skynet";
+
+// checks the message batch between the native side and dispatch_message: skynet.newservice("batch_test").
+// sizes just above 16MB go inline or external depending on js_zero_copy_size, run it with both
+const MB16 = 16 * 1024 * 1024;
+
+function check(cond: boolean, msg: string) {
+    if (!cond) {
+        throw new Error(`batch test failed: ${msg}`);
+    }
+}
+
+function check_decode() {
+    let buf = new Uint8Array(64);
+    let view = new DataView(buf.buffer);
+    let heads: [number, number, number, number, number][] = [
+        [skynet.PTYPE_ID.TEXT, 1, 0x01000001, 0, MB16 + 1],
+        [skynet.PTYPE_ID.LUA, 0xfffffffe, 0xff000010, 3, 0x100000000 + 5],
+    ];
+    for (let [prototype, session, source, external, sz] of heads) {
+        view.setUint32(0, prototype, true);
+        view.setUint32(4, session, true);
+        view.setUint32(8, source, true);
+        view.setUint32(12, external, true);
+        view.setUint32(16, sz % 0x100000000, true);
+        view.setUint32(20, Math.floor(sz / 0x100000000), true);
+        let head = skynet.decode_message_head(buf, 0);
+        check(head.prototype == prototype && head.session == session && head.source == source, `head ${JSON.stringify(head)}`);
+        check(head.external == external && head.sz == sz, `size ${head.sz} external ${head.external}, expected ${sz} ${external}`);
+    }
+}
+
+async function check_dispatch() {
+    let sizes = [100, MB16 + 1, 64, MB16 + 2, 0];
+    let received: [number, number, number][] = [];
+    let done: Function;
+    let all = new Promise((resolve) => done = resolve);
+    skynet.register_protocol({
+        id: skynet.PTYPE_ID.TEXT,
+        name: "text",
+        unpack: (buf: Uint8Array, offset: number, sz: number) => [sz, sz ? buf[offset] : 0, sz ? buf[offset + sz - 1] : 0],
+        dispatch: (context: skynet.CONTEXT, sz: number, first: number, last: number) => {
+            received.push([sz, first, last]);
+            if (received.length == sizes.length) {
+                done();
+            }
+        },
+    });
+
+    // sent back to back, so they arrive in one or a few batches
+    sizes.forEach((sz, i) => skynet.rawsend(skynet.self(), "text", new Uint8Array(sz).fill(i + 1)));
+    await all;
+    sizes.forEach((sz, i) => {
+        let [got, first, last] = received[i];
+        check(got == sz, `message ${i} size ${got}, expected ${sz}`);
+        check(sz == 0 || (first == i + 1 && last == i + 1), `message ${i} data ${first} ${last}`);
+    });
+}
+
+skynet.start(async () => {
+    check_decode();
+    await check_dispatch();
+    skynet.error("batch test ok");
+    skynet.exit();
+});
@@ -0,0 +1,24 @@
+import * as skynet from "skynet";
+
+// cold start of snjs services: skynet.newservice("startup_bench", "100") launches 100 children one by one.
+// run it before and after a startup change (snapshot, js_extensions) on the same node to compare.
+// skynet.newservice("startup_bench", "100", "none") launches the children with --js_extensions=none,
+// the snapshot always holds the js of every extension group and the bootstrap must not need their ops
+let [_, mode, extensions] = Skynet.init_args();
+
+skynet.start(async () => {
+    if (mode == "child") {
+        skynet.timeout(0, () => skynet.exit());
+        return;
+    }
+
+    let amount = Number(mode) || 100;
+    let options = extensions ? [`--js_extensions=${extensions}`] : [];
+    let start = Date.now();
+    for (let i = 0; i < amount; i++) {
+        await skynet.call(".launcher", "lua", "LAUNCH", "snjs", ...options, "startup_bench", "child");
+    }
+    let ms = Date.now() - start;
+    skynet.error(`startup bench amount:${amount} extensions:${extensions || "all"} ms:${ms} per service:${(ms / amount).toFixed(2)}ms`);
+    skynet.exit();
+});
@@ -4,7 +4,7 @@ import { assert } from "std/testing/asserts";
 import * as router from "x/router/mod";
 import * as ws from "std/ws/mod";
 
-let [_, listen_ip, listen_port] = JS_INIT_ARGS.split(" ");
+let [_, listen_ip, listen_port] = Skynet.init_args() as string[];
 let listen_addr = `${listen_ip == "0.0.0.0" ? "127.0.0.1" : listen_ip}:${listen_port}`;
 let PTYPE_INSPECTOR = 101;
 